@@ -1,9 +1,13 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use tokio::sync::mpsc::Sender;
 use tracing::{debug, info};
 
-use super::{endpoint::Endpoint, response::ClientResponse};
+use super::{
+    clock::{self, Clock},
+    endpoint::Endpoint,
+    response::ClientResponse,
+};
 
 pub enum CacheRequest {
     LastCalled(Endpoint, Sender<CacheResponse>),
@@ -26,14 +30,20 @@ pub struct ClientCache {
 pub struct CacheInner {
     last_called: HashMap<Endpoint, chrono::DateTime<chrono::Utc>>,
     last_response: HashMap<Endpoint, ClientResponse>,
+    clock: Arc<dyn Clock>,
 }
 
 impl ClientCache {
     const CLEANUP_INTERVAL: u64 = 100;
     pub fn new() -> Self {
+        Self::with_clock(clock::system())
+    }
+
+    /// A cache recording when endpoints were called by `clock`'s time.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
         let (tx, mut rx) = tokio::sync::mpsc::channel::<CacheRequest>(100);
         tokio::spawn(async move {
-            let mut inner = CacheInner::new();
+            let mut inner = CacheInner::new().with_clock(clock);
             let mut counter = 0;
             loop {
                 if let Some(request) = rx.recv().await {
@@ -94,11 +104,17 @@ impl CacheInner {
         Self {
             last_called: HashMap::new(),
             last_response: HashMap::new(),
+            clock: clock::system(),
         }
     }
 
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     fn cleanup(&mut self) {
-        let now = chrono::Utc::now();
+        let now = self.clock.now();
         let entries_to_clean: Vec<Endpoint> = self
             .last_called
             .iter()
@@ -160,8 +176,7 @@ impl CacheInner {
 
     pub fn handle_update(&mut self, endpoint: &Endpoint, response: ClientResponse) {
         debug!("Updating cache for {}", endpoint);
-        self.last_called
-            .insert(endpoint.clone(), chrono::Utc::now());
+        self.last_called.insert(endpoint.clone(), self.clock.now());
         self.last_response.insert(endpoint.clone(), response);
     }
 }
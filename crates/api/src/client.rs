@@ -1,4 +1,5 @@
 use rchan_types::{
+    archive::{Archive, ArchivedThread, ThreadStatus},
     board::Board,
     catalog::CatalogPage,
     index::Index,
@@ -7,10 +8,13 @@ use rchan_types::{
 use tracing::{debug, error};
 
 use super::{
-    cache::ClientCache, endpoint::Endpoint, error::Error, rate_limit::RateLimitedClient,
+    clock::{self, Clock},
+    endpoint::Endpoint,
+    error::Error,
     response::ClientResponse,
+    transport::{HttpTransport, Transport},
 };
-use std::sync::Arc;
+use std::{future::Future, pin::Pin, sync::Arc};
 
 /// Configuration for the client.
 /// use_https: Whether to use HTTPS for requests. (default: false)
@@ -40,11 +44,21 @@ impl Config {
     }
 }
 
+pub type GateFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Waits for the turn of each request a client makes, retries included,
+/// e.g. to share a request budget between clients.
+/// It replaces the rate limit of the client's transport.
+pub trait RequestGate: std::fmt::Debug + Send + Sync {
+    fn wait<'a>(&'a self, endpoint: &'a Endpoint) -> GateFuture<'a>;
+}
+
 #[derive(Debug, Clone)]
 pub struct Client {
     cfg: Config,
-    http: Arc<RateLimitedClient>,
-    cache: Arc<ClientCache>,
+    transport: Arc<dyn Transport>,
+    clock: Arc<dyn Clock>,
+    gate: Option<Arc<dyn RequestGate>>,
 }
 
 /// A client for interacting with the 4chan API.
@@ -57,34 +71,44 @@ pub struct Client {
 ///    your app over HTTPS.
 impl Client {
     pub fn new(cfg: Option<Config>) -> Self {
+        Self::with_transport(cfg, Arc::new(HttpTransport::new()))
+    }
+
+    /// A client fetching through the given transport instead of over HTTP.
+    pub fn with_transport(cfg: Option<Config>, transport: Arc<dyn Transport>) -> Self {
         Self {
             cfg: cfg.unwrap_or_default(),
-            http: Arc::new(RateLimitedClient::default()),
-            cache: Arc::new(ClientCache::new()),
+            transport,
+            clock: clock::system(),
+            gate: None,
         }
     }
 
-    async fn new_request(&self, endpoint: &Endpoint, https: bool) -> reqwest::Request {
-        let mut request =
-            reqwest::Request::new(reqwest::Method::GET, endpoint.url(https).parse().unwrap());
-        if let Some(time) = self.cache.last_called(endpoint.clone()).await {
-            request.headers_mut().insert(
-                reqwest::header::IF_MODIFIED_SINCE,
-                reqwest::header::HeaderValue::from_str(&time.to_rfc2822()).unwrap(),
-            );
-        }
-        request
+    /// Wait between retries by `clock`'s time, and share it with the workers using the client.
+    /// An `HttpTransport` rate limits by its own clock, see `HttpTransport::with_clock`.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Wait for `gate` before every request, instead of the transport's rate limit.
+    pub fn with_gate(mut self, gate: Arc<dyn RequestGate>) -> Self {
+        self.gate = Some(gate);
+        self
     }
 
     pub async fn get(&self, endpoint: &Endpoint, https: bool) -> Result<ClientResponse, Error> {
-        debug!("Sending request to {}", endpoint.url(https));
-        self.handle_response(
-            endpoint,
-            self.http
-                .execute(self.new_request(endpoint, https).await)
-                .await?,
-        )
-        .await
+        match &self.gate {
+            Some(gate) => {
+                gate.wait(endpoint).await;
+                self.transport.get_unlimited(endpoint, https).await
+            }
+            None => self.transport.get(endpoint, https).await,
+        }
     }
 
     pub async fn get_with_retry(
@@ -94,7 +118,9 @@ impl Client {
     ) -> Result<ClientResponse, Error> {
         let mut retries: usize = 0;
         loop {
-            tokio::time::sleep(std::time::Duration::from_secs(retries as u64)).await;
+            self.clock
+                .sleep(std::time::Duration::from_secs(retries as u64))
+                .await;
             match self.get(endpoint, https).await {
                 Ok(resp) => return Ok(resp),
                 Err(e) => {
@@ -122,33 +148,6 @@ impl Client {
         }
     }
 
-    pub async fn handle_response(
-        &self,
-        endpoint: &Endpoint,
-        resp: reqwest::Response,
-    ) -> Result<ClientResponse, Error> {
-        match resp.status() {
-            reqwest::StatusCode::OK => {
-                debug!("request: {} status: OK", endpoint);
-                let parsed = ClientResponse::parse(endpoint, resp).await?;
-                self.cache.update(endpoint.clone(), parsed.clone()).await;
-                Ok(parsed)
-            }
-            reqwest::StatusCode::NOT_MODIFIED => {
-                debug!("request: {} status: NOT_MODIFIED", endpoint);
-                Ok(self.cache.last_response(endpoint.clone()).await.unwrap())
-            }
-            reqwest::StatusCode::MOVED_PERMANENTLY => {
-                debug!("request: {} status: MOVED_PERMANENTLY", endpoint);
-                Err(Error::MovedPermanently)
-            }
-            _ => {
-                error!("request {} status: {}", endpoint, resp.status());
-                Err(Error::StatusCode(resp.status().as_u16().to_string()))
-            }
-        }
-    }
-
     pub async fn get_boards(&self) -> Result<Arc<Vec<Board>>, Error> {
         match self
             .get_with_retry(&Endpoint::Boards, self.cfg.use_https())
@@ -186,6 +185,59 @@ impl Client {
             })
     }
 
+    /// The board's archive, or `Error::NotArchived` if the board does not keep one.
+    pub async fn get_board_archive(&self, board: &Board) -> Result<Archive, Error> {
+        if !board.capabilities().archived {
+            return Err(Error::NotArchived(board.name().to_string()));
+        }
+        let threads = self.get_archive(board.name()).await?;
+        Ok(Archive::new(board.name().to_string(), threads.to_vec()))
+    }
+
+    /// Fetch a page of archived threads, most recently archived first.
+    /// Threads that disappeared from the archive in the meantime are skipped.
+    pub async fn get_archived_threads(
+        &self,
+        board: &Board,
+        page: usize,
+        per_page: usize,
+    ) -> Result<Vec<ArchivedThread>, Error> {
+        let archive = self.get_board_archive(board).await?;
+        let mut threads = vec![];
+        for no in archive.page(page, per_page).iter().rev() {
+            match self.get_thread(board.name(), *no).await {
+                Ok(thread) => threads.push(ArchivedThread {
+                    no: *no,
+                    archived_on: thread.archived_on(),
+                    thread,
+                }),
+                Err(Error::StatusCode(code)) if code == "404" => {
+                    debug!("Archived thread {} on {} is gone", no, board.name());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(threads)
+    }
+
+    /// Find out what happened to a thread.
+    /// A thread that 404s is archived if the board's archive lists it,
+    /// otherwise it was pruned or deleted.
+    /// Classifying several threads, fetch the archive once and use `Archive::status`.
+    pub async fn get_thread_status(&self, board: &Board, no: i32) -> Result<ThreadStatus, Error> {
+        match self.get_thread(board.name(), no).await {
+            Ok(thread) => Ok(thread.status()),
+            Err(Error::StatusCode(code)) if code == "404" => {
+                match self.get_board_archive(board).await {
+                    Ok(archive) => Ok(archive.status(no)),
+                    Err(Error::NotArchived(_)) => Ok(ThreadStatus::Removed),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn get_index(&self, board: &str, page: i32) -> Result<Arc<Index>, Error> {
         self.get_with_retry(
             &Endpoint::Index(board.to_string(), page),
@@ -295,6 +347,19 @@ mod tests {
         debug!("{:?}", resp);
     }
 
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_board_archive_not_archived() {
+        let client = Client::default();
+        let board = Board {
+            board: "b".to_string(),
+            is_archived: None,
+            ..Default::default()
+        };
+        let resp = client.get_board_archive(&board).await;
+        assert!(matches!(resp, Err(Error::NotArchived(_))));
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     async fn test_get_index() {
@@ -304,6 +369,35 @@ mod tests {
         debug!("{:?}", resp);
     }
 
+    /// Counts the requests it lets through.
+    #[derive(Debug, Default)]
+    struct CountingGate(std::sync::atomic::AtomicUsize);
+
+    impl RequestGate for CountingGate {
+        fn wait<'a>(&'a self, _endpoint: &'a Endpoint) -> GateFuture<'a> {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Box::pin(async {})
+        }
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test(start_paused = true)]
+    async fn test_request_gate() {
+        let transport = Arc::new(crate::transport::ScriptedTransport::new());
+        let endpoint = Endpoint::Threads("g".to_string());
+        transport.push_status(endpoint.clone(), 500);
+        transport.push_status(endpoint.clone(), 500);
+        transport.push(endpoint.clone(), ClientResponse::Threads(Arc::new(vec![])));
+        let gate = Arc::new(CountingGate::default());
+        let client = Client::with_transport(Some(Config::new(None, Some(5))), transport.clone())
+            .with_gate(gate.clone());
+
+        client.get_threads("g").await.unwrap();
+        // Every attempt waits for the gate, not just the first.
+        assert_eq!(transport.requests().len(), 3);
+        assert_eq!(gate.0.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     async fn test_get_thread() {
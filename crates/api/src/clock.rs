@@ -0,0 +1,156 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+
+pub type ClockFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Source of the current time, and of sleeps measured against it.
+/// Everything that polls, rate limits or times out takes its time from a clock,
+/// so tests can swap the system clock for one they control.
+pub trait Clock: std::fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+    fn sleep(&self, duration: Duration) -> ClockFuture;
+
+    fn now_ms(&self) -> i64 {
+        self.now().timestamp_millis()
+    }
+}
+
+/// Wall clock time, and tokio sleeps.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> ClockFuture {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Wall clock time when created, moving with tokio's clock from then on.
+/// Under `tokio::time::pause` time only passes while every task sleeps,
+/// and then jumps to the next sleep's deadline, so hours of polling take milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub struct TokioClock {
+    start: DateTime<Utc>,
+    started: tokio::time::Instant,
+}
+
+impl TokioClock {
+    pub fn new() -> TokioClock {
+        TokioClock {
+            start: Utc::now(),
+            started: tokio::time::Instant::now(),
+        }
+    }
+}
+
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TokioClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.start + chrono::Duration::from_std(self.started.elapsed()).unwrap_or_default()
+    }
+
+    fn sleep(&self, duration: Duration) -> ClockFuture {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A clock that only moves when told to.
+/// Sleeps end once the clock is advanced past their deadline.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now_ms: Arc<tokio::sync::watch::Sender<i64>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> ManualClock {
+        let (now_ms, _) = tokio::sync::watch::channel(now.timestamp_millis());
+        ManualClock {
+            now_ms: Arc::new(now_ms),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let ms = duration.as_millis() as i64;
+        self.now_ms.send_modify(|now_ms| *now_ms += ms);
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.now_ms.send_replace(now.timestamp_millis());
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(*self.now_ms.borrow()).unwrap_or_default()
+    }
+
+    fn sleep(&self, duration: Duration) -> ClockFuture {
+        let deadline = self.now_ms() + duration.as_millis() as i64;
+        let mut now_ms = self.now_ms.subscribe();
+        Box::pin(async move {
+            let _ = now_ms.wait_for(|now_ms| *now_ms >= deadline).await;
+        })
+    }
+}
+
+/// Run `fut` for at most `duration` of `clock`'s time.
+/// Returns None if it didn't finish in time.
+pub async fn timeout<F: Future>(
+    clock: &dyn Clock,
+    duration: Duration,
+    fut: F,
+) -> Option<F::Output> {
+    tokio::select! {
+        output = fut => Some(output),
+        _ = clock.sleep(duration) => None,
+    }
+}
+
+/// The clock used unless another one is given.
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_manual_clock() {
+        let clock = ManualClock::default();
+        let start = clock.now_ms();
+        let sleep = tokio::spawn(clock.sleep(Duration::from_secs(3600)));
+        clock.advance(Duration::from_secs(1800));
+        tokio::task::yield_now().await;
+        assert!(!sleep.is_finished());
+        clock.advance(Duration::from_secs(1800));
+        sleep.await.unwrap();
+        assert_eq!(clock.now_ms() - start, 3_600_000);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test(start_paused = true)]
+    async fn test_tokio_clock() {
+        let clock = TokioClock::new();
+        let start = clock.now_ms();
+        clock.sleep(Duration::from_secs(3600)).await;
+        assert_eq!(clock.now_ms() - start, 3_600_000);
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt::{Display, Formatter};
 
+use rchan_types::link::Link;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Endpoint {
@@ -29,7 +30,21 @@ impl Endpoint {
             self.http()
         }
     }
+}
 
+/// The API endpoint serving the data behind a web link.
+/// Post links resolve to their thread.
+impl From<&Link> for Endpoint {
+    fn from(link: &Link) -> Self {
+        match link {
+            Link::Board { board, page } => Self::Index(board.clone(), page.unwrap_or(1)),
+            Link::Catalog { board } => Self::Catalog(board.clone()),
+            Link::Archive { board } => Self::Archive(board.clone()),
+            Link::Thread { board, thread, .. } | Link::Post { board, thread, .. } => {
+                Self::Thread(board.clone(), *thread)
+            }
+        }
+    }
 }
 
 impl Display for Endpoint {
@@ -50,3 +65,22 @@ impl Display for Endpoint {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_link() {
+        let link =
+            Link::parse("https://boards.4chan.org/g/thread/99566851/slug#p99567000").unwrap();
+        assert_eq!(
+            Endpoint::from(&link),
+            Endpoint::Thread("g".to_string(), 99566851)
+        );
+        assert_eq!(link.post_no(), Some(99567000));
+
+        let link = Link::parse("https://boards.4chan.org/g/").unwrap();
+        assert_eq!(Endpoint::from(&link), Endpoint::Index("g".to_string(), 1));
+    }
+}
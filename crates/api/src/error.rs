@@ -21,4 +21,7 @@ pub enum Error {
     #[error("Moved permanently")]
     MovedPermanently,
 
+    #[error("Board is not archived: {0}")]
+    NotArchived(String),
+
 }
@@ -1,6 +1,9 @@
 pub mod client;
+pub mod clock;
 pub mod rate_limit;
 pub mod endpoint;
 pub mod response;
 pub mod cache;
 pub mod error;
+pub mod pagination;
+pub mod transport;
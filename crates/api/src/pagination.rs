@@ -0,0 +1,289 @@
+use std::sync::Arc;
+
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use rchan_types::{
+    archive::ArchivedThread,
+    board::Board,
+    index::Index,
+    post::{Post, Thread},
+};
+
+use super::{client::Client, error::Error};
+
+/// Stream adapters over paginated board data.
+/// Every request goes through the client, so the rate limit still applies;
+/// items are yielded as soon as the page or thread containing them arrives.
+impl Client {
+    /// Every thread OP in the board's catalog.
+    pub fn catalog_threads(&self, board: &str) -> impl Stream<Item = Result<Post, Error>> {
+        let client = self.clone();
+        let board = board.to_string();
+        stream::once(async move { client.get_catalog(&board).await })
+            .map_ok(|pages| {
+                stream::iter(
+                    pages
+                        .iter()
+                        .flat_map(|page| page.threads.iter().cloned().map(Ok))
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .try_flatten()
+    }
+
+    /// Every index page of the board in order, fetching up to `concurrency` pages ahead
+    /// as the stream is polled.
+    /// Pages that 404, e.g. on a board with fewer threads than it has pages, are skipped.
+    pub fn index_pages(
+        &self,
+        board: &Board,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<Arc<Index>, Error>> {
+        let client = self.clone();
+        let pages = board.pages;
+        let board = board.name().to_string();
+        stream::iter(1..=pages)
+            .map(move |page| {
+                let client = client.clone();
+                let board = board.clone();
+                async move { skip_not_found(client.get_index(&board, page).await) }
+            })
+            .buffered(concurrency.max(1))
+            .try_filter_map(|index| async move { Ok(index) })
+    }
+
+    /// Every live thread on the board, fetching up to `concurrency` threads at a time.
+    /// Threads that 404 before they could be fetched are skipped.
+    pub fn live_threads(
+        &self,
+        board: &str,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<Arc<Thread>, Error>> {
+        let client = self.clone();
+        let board = board.to_string();
+        stream::once(async move {
+            let pages = client.get_threads(&board).await?;
+            let nos = pages
+                .iter()
+                .flat_map(|page| page.threads.iter().map(|t| t.no))
+                .collect::<Vec<_>>();
+            Ok::<_, Error>((client, board, nos))
+        })
+        .map_ok(move |(client, board, nos)| {
+            stream::iter(nos)
+                .map(move |no| {
+                    let client = client.clone();
+                    let board = board.clone();
+                    async move { skip_not_found(client.get_thread(&board, no).await) }
+                })
+                .buffer_unordered(concurrency.max(1))
+        })
+        .try_flatten()
+        .try_filter_map(|thread| async move { Ok(thread) })
+    }
+
+    /// Every post of every live thread on the board.
+    pub fn live_posts(
+        &self,
+        board: &str,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<Post, Error>> {
+        self.live_threads(board, concurrency)
+            .map_ok(|thread| stream::iter(thread.posts.clone().into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Every thread in the board's archive, most recently archived first,
+    /// fetching up to `concurrency` threads at a time.
+    pub fn archived_threads(
+        &self,
+        board: &Board,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<ArchivedThread, Error>> {
+        let client = self.clone();
+        let board = board.clone();
+        stream::once(async move {
+            let archive = client.get_board_archive(&board).await?;
+            Ok::<_, Error>((client, board, archive))
+        })
+        .map_ok(move |(client, board, archive)| {
+            stream::iter(archive.threads.into_iter().rev())
+                .map(move |no| {
+                    let client = client.clone();
+                    let board = board.name().to_string();
+                    async move {
+                        skip_not_found(client.get_thread(&board, no).await).map(|thread| {
+                            thread.map(|thread| ArchivedThread {
+                                no,
+                                archived_on: thread.archived_on(),
+                                thread,
+                            })
+                        })
+                    }
+                })
+                .buffer_unordered(concurrency.max(1))
+        })
+        .try_flatten()
+        .try_filter_map(|thread| async move { Ok(thread) })
+    }
+}
+
+fn skip_not_found<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::StatusCode(code)) if code == "404" => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::Config,
+        endpoint::Endpoint,
+        response::ClientResponse,
+        transport::{ScriptedTransport, Transport, TransportFuture},
+    };
+    use rchan_types::{catalog::CatalogPage, index::IndexThread, post::ThreadPage};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serves scripted responses after a delay, recording how many requests overlapped.
+    #[derive(Debug, Default)]
+    struct SlowTransport {
+        scripted: ScriptedTransport,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl Transport for SlowTransport {
+        fn get<'a>(&'a self, endpoint: &'a Endpoint, https: bool) -> TransportFuture<'a> {
+            Box::pin(async move {
+                let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                self.scripted.get(endpoint, https).await
+            })
+        }
+    }
+
+    fn client(transport: Arc<dyn Transport>) -> Client {
+        Client::with_transport(Some(Config::new(None, Some(0))), transport)
+    }
+
+    fn post(no: i32, resto: i32) -> Post {
+        Post {
+            no,
+            resto: Some(resto),
+            ..Default::default()
+        }
+    }
+
+    fn thread(nos: &[i32]) -> ClientResponse {
+        ClientResponse::Thread(Arc::new(Thread {
+            posts: nos
+                .iter()
+                .map(|no| post(*no, if *no == nos[0] { 0 } else { nos[0] }))
+                .collect(),
+        }))
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_catalog_threads() {
+        let transport = Arc::new(ScriptedTransport::new());
+        transport.push(
+            Endpoint::Catalog("g".to_string()),
+            ClientResponse::Catalog(Arc::new(vec![
+                CatalogPage {
+                    page: 1,
+                    threads: vec![post(1, 0), post(2, 0)],
+                },
+                CatalogPage {
+                    page: 2,
+                    threads: vec![post(3, 0)],
+                },
+            ])),
+        );
+        let threads = client(transport)
+            .catalog_threads("g")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            threads.iter().map(|t| t.no).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test(start_paused = true)]
+    async fn test_index_pages() {
+        let transport = Arc::new(SlowTransport::default());
+        for page in [1, 2, 4] {
+            transport.scripted.push(
+                Endpoint::Index("g".to_string(), page),
+                ClientResponse::Index(Arc::new(Index {
+                    threads: vec![IndexThread {
+                        posts: vec![post(page, 0)],
+                    }],
+                })),
+            );
+        }
+        let board = Board {
+            board: "g".to_string(),
+            pages: 4,
+            ..Default::default()
+        };
+        let pages = client(transport.clone())
+            .index_pages(&board, 2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        // Page 3 404s and is skipped, the others keep their order.
+        assert_eq!(
+            pages
+                .iter()
+                .map(|index| index.threads[0].posts[0].no)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 4]
+        );
+        assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test(start_paused = true)]
+    async fn test_live_posts() {
+        let transport = Arc::new(SlowTransport::default());
+        transport.scripted.push(
+            Endpoint::Threads("g".to_string()),
+            ClientResponse::Threads(Arc::new(vec![ThreadPage {
+                page: 1,
+                threads: vec![post(1, 0), post(5, 0), post(8, 0), post(9, 0)],
+            }])),
+        );
+        transport
+            .scripted
+            .push(Endpoint::Thread("g".to_string(), 1), thread(&[1, 2, 3]));
+        transport
+            .scripted
+            .push(Endpoint::Thread("g".to_string(), 5), thread(&[5, 6]));
+        transport
+            .scripted
+            .push(Endpoint::Thread("g".to_string(), 9), thread(&[9]));
+        transport
+            .scripted
+            .push_status(Endpoint::Thread("g".to_string(), 8), 404);
+
+        let mut posts = client(transport.clone())
+            .live_posts("g", 3)
+            .map_ok(|post| post.no)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        posts.sort();
+        // Thread 8 404d after the thread list was fetched.
+        assert_eq!(posts, vec![1, 2, 3, 5, 6, 9]);
+        assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 3);
+    }
+}
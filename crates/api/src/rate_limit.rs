@@ -1,11 +1,16 @@
+use std::sync::Arc;
+
 use tokio::sync::mpsc::Sender;
 use tracing::{debug, error};
 
+use super::clock::{self, Clock};
+
 #[derive(Debug, Clone)]
 pub struct RateLimiter {
     rate_limit_per_interval: usize,
     interval_duration_ms: u128,
     timestamps: Vec<u128>,
+    clock: Arc<dyn Clock>,
 }
 
 impl RateLimiter {
@@ -15,18 +20,21 @@ impl RateLimiter {
             rate_limit_per_interval,
             interval_duration_ms,
             timestamps: vec![],
+            clock: clock::system(),
         }
     }
 
-    fn now() -> u128 {
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis()
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn now(&self) -> u128 {
+        self.clock.now_ms().max(0) as u128
     }
 
     async fn rate_limit(&mut self) {
-        let now = Self::now();
+        let now = self.now();
         self.timestamps = self
             .timestamps
             .iter()
@@ -38,7 +46,9 @@ impl RateLimiter {
             debug!("Rate limiting: sleeping for {} ms", sleep_duration);
             self.timestamps
                 .push(now + sleep_duration + self.interval_duration_ms);
-            tokio::time::sleep(std::time::Duration::from_millis(sleep_duration as u64)).await;
+            self.clock
+                .sleep(std::time::Duration::from_millis(sleep_duration as u64))
+                .await;
         } else {
             self.timestamps.push(now + self.interval_duration_ms);
         }
@@ -52,14 +62,28 @@ pub struct RateLimitedClient {
 
 enum ClientRequest {
     Get(String, Sender<reqwest::Result<reqwest::Response>>),
-    Execute(reqwest::Request, Sender<reqwest::Result<reqwest::Response>>),
+    Execute(Box<reqwest::Request>, Sender<reqwest::Result<reqwest::Response>>),
 }
 
 impl RateLimitedClient {
     pub fn new(rate_limit_per_interval: usize, interval_duration_ms: u128) -> Self {
+        Self::with_clock(
+            rate_limit_per_interval,
+            interval_duration_ms,
+            clock::system(),
+        )
+    }
+
+    /// A client spacing its requests out by `clock`'s time.
+    pub fn with_clock(
+        rate_limit_per_interval: usize,
+        interval_duration_ms: u128,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
         let (tx, mut rx) = tokio::sync::mpsc::channel(100);
         tokio::spawn(async move {
-            let mut rl = RateLimiter::new(rate_limit_per_interval, interval_duration_ms);
+            let mut rl =
+                RateLimiter::new(rate_limit_per_interval, interval_duration_ms).with_clock(clock);
             let client = reqwest::Client::new();
             loop {
                 if let Some(req) = rx.recv().await {
@@ -70,7 +94,7 @@ impl RateLimitedClient {
                             Self::handle_get(client, tx, url).await;
                         }
                         ClientRequest::Execute(req, tx) => {
-                            Self::handle_execute(client, tx, req).await;
+                            Self::handle_execute(client, tx, *req).await;
                         }
                     }
                 }
@@ -118,7 +142,7 @@ impl RateLimitedClient {
     ) -> reqwest::Result<reqwest::Response> {
         let (tx, mut rx) = tokio::sync::mpsc::channel(1);
         self.receiver
-            .send(ClientRequest::Execute(request, tx))
+            .send(ClientRequest::Execute(Box::new(request), tx))
             .await
             .map_err(|e| error!("{:?}", e))
             .unwrap();
@@ -138,6 +162,7 @@ mod tests {
     use std::time::SystemTime;
 
     use super::*;
+    use crate::clock::{ManualClock, TokioClock};
 
     #[tokio::test]
     async fn test_client() {
@@ -147,22 +172,36 @@ mod tests {
     }
 
     #[tracing_test::traced_test]
-    #[tokio::test]
+    #[tokio::test(start_paused = true)]
     async fn test_rate_limiter() {
         let rate_limit_per_interval = 1;
         let interval_duration_ms = 1000;
         let n = 3;
 
-        let mut rate_limiter = RateLimiter::new(rate_limit_per_interval, interval_duration_ms);
-        let now = SystemTime::now();
+        let clock = Arc::new(TokioClock::new());
+        let mut rate_limiter = RateLimiter::new(rate_limit_per_interval, interval_duration_ms)
+            .with_clock(clock.clone());
+        let now = clock.now_ms();
         for _ in 0..n {
             rate_limiter.rate_limit().await;
         }
-        debug!("elapsed: {}", now.elapsed().unwrap().as_millis());
-        assert!(
-            now.elapsed().unwrap().as_millis()
-                >= (n - 1) / rate_limit_per_interval as u128 * interval_duration_ms
-        );
+        let elapsed = (clock.now_ms() - now) as u128;
+        debug!("elapsed: {}", elapsed);
+        assert!(elapsed >= (n - 1) / rate_limit_per_interval as u128 * interval_duration_ms);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_rate_limiter_manual_clock() {
+        let clock = ManualClock::default();
+        let mut rate_limiter = RateLimiter::new(2, 1000).with_clock(Arc::new(clock.clone()));
+        rate_limiter.rate_limit().await;
+        rate_limiter.rate_limit().await;
+        let limited = tokio::spawn(async move { rate_limiter.rate_limit().await });
+        tokio::task::yield_now().await;
+        assert!(!limited.is_finished());
+        clock.advance(std::time::Duration::from_millis(1000));
+        limited.await.unwrap();
     }
 
     #[tracing_test::traced_test]
@@ -0,0 +1,237 @@
+use std::{future::Future, pin::Pin, sync::Arc};
+#[cfg(any(test, feature = "test-util"))]
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use tracing::{debug, error};
+
+use super::{
+    cache::ClientCache,
+    clock::{self, Clock},
+    endpoint::Endpoint,
+    error::Error,
+    rate_limit::RateLimitedClient,
+    response::ClientResponse,
+};
+
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<ClientResponse, Error>> + Send + 'a>>;
+
+/// Fetches and parses a single endpoint.
+/// The client handles retries on top of this.
+pub trait Transport: std::fmt::Debug + Send + Sync {
+    fn get<'a>(&'a self, endpoint: &'a Endpoint, https: bool) -> TransportFuture<'a>;
+
+    /// Fetch without the transport's own rate limit, if it has one,
+    /// for a client whose `RequestGate` spaces requests out instead.
+    fn get_unlimited<'a>(&'a self, endpoint: &'a Endpoint, https: bool) -> TransportFuture<'a> {
+        self.get(endpoint, https)
+    }
+}
+
+/// Fetches endpoints from the 4chan API over HTTP,
+/// rate limited and using If-Modified-Since.
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    http: Arc<RateLimitedClient>,
+    unlimited: reqwest::Client,
+    cache: Arc<ClientCache>,
+}
+
+impl HttpTransport {
+    pub fn new() -> Self {
+        Self::with_clock(clock::system())
+    }
+
+    /// A transport rate limiting, and recording when endpoints were called, by `clock`'s time.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            http: Arc::new(RateLimitedClient::with_clock(
+                1,
+                std::time::Duration::from_secs(1).as_millis(),
+                clock.clone(),
+            )),
+            unlimited: reqwest::Client::new(),
+            cache: Arc::new(ClientCache::with_clock(clock)),
+        }
+    }
+
+    async fn new_request(&self, endpoint: &Endpoint, https: bool) -> reqwest::Request {
+        let mut request =
+            reqwest::Request::new(reqwest::Method::GET, endpoint.url(https).parse().unwrap());
+        if let Some(time) = self.cache.last_called(endpoint.clone()).await {
+            request.headers_mut().insert(
+                reqwest::header::IF_MODIFIED_SINCE,
+                reqwest::header::HeaderValue::from_str(&time.to_rfc2822()).unwrap(),
+            );
+        }
+        request
+    }
+
+    pub async fn fetch(&self, endpoint: &Endpoint, https: bool) -> Result<ClientResponse, Error> {
+        debug!("Sending request to {}", endpoint.url(https));
+        self.handle_response(
+            endpoint,
+            self.http
+                .execute(self.new_request(endpoint, https).await)
+                .await?,
+        )
+        .await
+    }
+
+    /// Fetch right away, bypassing the rate limit.
+    pub async fn fetch_unlimited(
+        &self,
+        endpoint: &Endpoint,
+        https: bool,
+    ) -> Result<ClientResponse, Error> {
+        debug!("Sending unlimited request to {}", endpoint.url(https));
+        self.handle_response(
+            endpoint,
+            self.unlimited
+                .execute(self.new_request(endpoint, https).await)
+                .await?,
+        )
+        .await
+    }
+
+    pub async fn handle_response(
+        &self,
+        endpoint: &Endpoint,
+        resp: reqwest::Response,
+    ) -> Result<ClientResponse, Error> {
+        match resp.status() {
+            reqwest::StatusCode::OK => {
+                debug!("request: {} status: OK", endpoint);
+                let parsed = ClientResponse::parse(endpoint, resp).await?;
+                self.cache.update(endpoint.clone(), parsed.clone()).await;
+                Ok(parsed)
+            }
+            reqwest::StatusCode::NOT_MODIFIED => {
+                debug!("request: {} status: NOT_MODIFIED", endpoint);
+                Ok(self.cache.last_response(endpoint.clone()).await.unwrap())
+            }
+            reqwest::StatusCode::MOVED_PERMANENTLY => {
+                debug!("request: {} status: MOVED_PERMANENTLY", endpoint);
+                Err(Error::MovedPermanently)
+            }
+            _ => {
+                error!("request {} status: {}", endpoint, resp.status());
+                Err(Error::StatusCode(resp.status().as_u16().to_string()))
+            }
+        }
+    }
+}
+
+impl Default for HttpTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for HttpTransport {
+    fn get<'a>(&'a self, endpoint: &'a Endpoint, https: bool) -> TransportFuture<'a> {
+        Box::pin(self.fetch(endpoint, https))
+    }
+
+    fn get_unlimited<'a>(&'a self, endpoint: &'a Endpoint, https: bool) -> TransportFuture<'a> {
+        Box::pin(self.fetch_unlimited(endpoint, https))
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone)]
+enum Scripted {
+    Response(ClientResponse),
+    Status(u16),
+}
+
+/// A transport serving scripted responses, for tests, with the `test-util` feature.
+/// Responses for an endpoint are served in the order they were pushed,
+/// the last one is repeated once the others are used up.
+/// Endpoints without responses return a 404.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Default)]
+pub struct ScriptedTransport {
+    responses: Mutex<HashMap<Endpoint, VecDeque<Scripted>>>,
+    requests: Mutex<Vec<Endpoint>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl ScriptedTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, endpoint: Endpoint, response: ClientResponse) {
+        self.push_scripted(endpoint, Scripted::Response(response));
+    }
+
+    /// Respond with an error status code.
+    pub fn push_status(&self, endpoint: Endpoint, status: u16) {
+        self.push_scripted(endpoint, Scripted::Status(status));
+    }
+
+    fn push_scripted(&self, endpoint: Endpoint, scripted: Scripted) {
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(endpoint)
+            .or_default()
+            .push_back(scripted);
+    }
+
+    /// Every endpoint requested so far, in order.
+    pub fn requests(&self) -> Vec<Endpoint> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn next(&self, endpoint: &Endpoint) -> Result<ClientResponse, Error> {
+        self.requests.lock().unwrap().push(endpoint.clone());
+        let mut responses = self.responses.lock().unwrap();
+        let scripted = match responses.get_mut(endpoint) {
+            Some(queue) if queue.len() > 1 => queue.pop_front(),
+            Some(queue) => queue.front().cloned(),
+            None => None,
+        };
+        match scripted {
+            Some(Scripted::Response(response)) => Ok(response),
+            Some(Scripted::Status(status)) => Err(Error::StatusCode(status.to_string())),
+            None => Err(Error::StatusCode("404".to_string())),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Transport for ScriptedTransport {
+    fn get<'a>(&'a self, endpoint: &'a Endpoint, _https: bool) -> TransportFuture<'a> {
+        let response = self.next(endpoint);
+        Box::pin(async move { response })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scripted_transport() {
+        let transport = ScriptedTransport::new();
+        let endpoint = Endpoint::Archive("g".to_string());
+        transport.push(endpoint.clone(), ClientResponse::Archive(Arc::new(vec![1])));
+        transport.push_status(endpoint.clone(), 500);
+
+        let resp = transport.get(&endpoint, false).await.unwrap();
+        assert!(matches!(resp, ClientResponse::Archive(a) if a[0] == 1));
+        let resp = transport.get(&endpoint, false).await;
+        assert!(matches!(resp, Err(Error::StatusCode(code)) if code == "500"));
+        let resp = transport.get(&endpoint, false).await;
+        assert!(matches!(resp, Err(Error::StatusCode(code)) if code == "500"));
+
+        let resp = transport.get(&Endpoint::Boards, false).await;
+        assert!(matches!(resp, Err(Error::StatusCode(code)) if code == "404"));
+        assert_eq!(transport.requests().len(), 4);
+    }
+}
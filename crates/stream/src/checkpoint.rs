@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::error::Error;
+
+/// High-water marks of a board, enough to pick up where a worker left off.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub board: String,
+    /// Highest post number emitted on the board.
+    pub last_post_no: i32,
+    /// Highest post number emitted per live thread.
+    pub threads: HashMap<i32, i32>,
+    /// Sequence number of the last event sent, see `Event::seq`.
+    #[serde(default)]
+    pub seq: u64,
+}
+
+impl Checkpoint {
+    /// Highest post number emitted for a thread.
+    /// Threads not in the checkpoint were created after it,
+    /// or were quiet since before it, so the board's mark applies.
+    pub fn thread_last_post_no(&self, no: i32) -> i32 {
+        self.threads.get(&no).copied().unwrap_or(self.last_post_no)
+    }
+}
+
+/// Where workers persist their checkpoints.
+pub trait CheckpointStore: Debug + Send + Sync {
+    fn load(&self, board: &str) -> Result<Option<Checkpoint>, Error>;
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), Error>;
+}
+
+/// Stores checkpoints as one JSON file per board in a directory.
+/// Files are replaced atomically, so a crash mid-save keeps the previous checkpoint.
+#[derive(Debug, Clone)]
+pub struct FileCheckpointStore {
+    dir: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new(dir: impl Into<PathBuf>) -> FileCheckpointStore {
+        FileCheckpointStore { dir: dir.into() }
+    }
+
+    fn path(&self, board: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", board))
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(&self, board: &str) -> Result<Option<Checkpoint>, Error> {
+        match std::fs::read(self.path(board)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), Error> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(&checkpoint.board);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(checkpoint)?)?;
+        std::fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Keeps checkpoints in memory, for as long as the process runs.
+/// Supervised workers use one to hand their progress to their replacements.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryCheckpointStore {
+    checkpoints: Arc<Mutex<HashMap<String, Checkpoint>>>,
+}
+
+impl MemoryCheckpointStore {
+    pub fn new() -> MemoryCheckpointStore {
+        MemoryCheckpointStore::default()
+    }
+}
+
+impl CheckpointStore for MemoryCheckpointStore {
+    fn load(&self, board: &str) -> Result<Option<Checkpoint>, Error> {
+        Ok(self.checkpoints.lock().unwrap().get(board).cloned())
+    }
+
+    fn save(&self, checkpoint: &Checkpoint) -> Result<(), Error> {
+        self.checkpoints
+            .lock()
+            .unwrap()
+            .insert(checkpoint.board.clone(), checkpoint.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("rchan-checkpoints-{}", std::process::id()));
+        let store = FileCheckpointStore::new(&dir);
+        assert_eq!(store.load("g").unwrap(), None);
+
+        let checkpoint = Checkpoint {
+            board: "g".to_string(),
+            last_post_no: 10,
+            threads: HashMap::from([(1, 10), (5, 7)]),
+            seq: 3,
+        };
+        store.save(&checkpoint).unwrap();
+        let loaded = store.load("g").unwrap().unwrap();
+        assert_eq!(loaded, checkpoint);
+        assert_eq!(loaded.thread_last_post_no(5), 7);
+        assert_eq!(loaded.thread_last_post_no(11), 10);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,68 +1,441 @@
-use super::error::Error;
-use rchan_api::client::Client;
+use super::{
+    checkpoint::{CheckpointStore, MemoryCheckpointStore},
+    error::Error,
+    events::{channel, Backpressure, Broadcast, EventSender, EventStream, QueueCounters},
+    filter::Filter,
+    scheduler::Scheduler,
+    site::SiteWorker,
+    supervisor::{Supervised, Supervisor},
+    thread_worker::ThreadWorker,
+    worker::{BoardCache, BoardWorker, WorkerStatus},
+};
+use rchan_api::{client::Client, clock};
 use rchan_types::board::Board;
-use std::{collections::HashMap, sync::Arc};
-use tracing::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    sync::Arc,
+};
+use tracing::{debug, error, info};
 
+/// Subscription to a whole board, or to a single thread if `thread_no` is set,
+/// or to every board of the site if `all_boards_refresh_ms` is set.
+/// Thread subscriptions back off from `refresh_rate_ms` while the thread is quiet.
+/// track_deletions: Emit `PostDeleted` and `FileDeleted` events. Keeps the last fetched
+///                  version of every thread in memory. (default: false)
+/// track_pages: Emit `ThreadChange::PageChanged` whenever a thread moves to another page,
+///              which most threads do on every poll. (default: false)
+/// buffer: Number of events buffered for slow consumers. (default: 100)
+/// backpressure: What to do with new events when the buffer is full. (default: Block)
+/// adaptive_refresh: Adjust the board refresh rate between these bounds based on
+///                   board activity, starting from refresh_rate_ms. (default: None)
+/// filter: Only send events about posts matching this filter. (default: None)
+/// checkpoint_interval_ms: How often board workers save a checkpoint, if the stream
+///                         has a checkpoint store. (default: 60000)
+/// poll_catalog: Poll catalog.json instead of threads.json, sending new threads and replies
+///               from its previews and fetching only threads with more new replies than
+///               the preview shows. Threads are always fetched with track_deletions.
+///               (default: false)
+/// shutdown_timeout_ms: How long in-flight fetches may take to finish, and events to be
+///                      flushed, when the stream shuts down. (default: 5000)
+/// backfill: Before sending new posts, send the posts already made, as historical events
+///           in post number order. Skipped when resuming from a checkpoint. (default: None)
+/// order: The order a board's events are sent in within a polling cycle. (default: Arrival)
+/// all_boards_refresh_ms: Watch every board instead of `board_name`, refreshing the list
+///                        of boards this often. Every board gets the other settings.
+///                        (default: None, 600000 with `Subscription::all_boards`)
 #[derive(Debug, Clone)]
 pub struct Subscription {
     pub board_name: String,
     pub refresh_rate_ms: i64,
+    pub thread_no: Option<i32>,
+    pub track_deletions: bool,
+    pub track_pages: bool,
+    pub buffer: usize,
+    pub backpressure: Backpressure,
+    pub adaptive_refresh: Option<RefreshRange>,
+    pub filter: Option<Filter>,
+    pub checkpoint_interval_ms: i64,
+    pub shutdown_timeout_ms: i64,
+    pub poll_catalog: bool,
+    pub all_boards_refresh_ms: Option<i64>,
+    pub backfill: Backfill,
+    pub order: EventOrder,
+}
+
+/// Which posts made before a subscription started to send.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backfill {
+    #[default]
+    None,
+    /// Every post of the threads currently on the board.
+    Live,
+    /// Every post of the live threads and of the threads in the board's archive.
+    /// The whole archive is fetched, which takes a while.
+    LiveAndArchived,
+}
+
+/// The order a board worker sends a polling cycle's events in.
+/// Events are numbered in the order they are sent, see `Event::seq`.
+/// Sorted events about threads rather than posts, such as `ThreadChanged`, come first.
+/// Sorting is per board: each board's cycles are sorted on their own,
+/// so the events of different subscriptions still interleave.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EventOrder {
+    /// As each thread's fetch finishes, so threads interleave.
+    #[default]
+    Arrival,
+    /// Buffered until the cycle ends, then sorted by post number.
+    PostNumber,
+    /// Buffered until the cycle ends, then sorted by post time, then number.
+    PostTime,
+}
+
+/// Settings of a running subscription, sent to its worker.
+#[derive(Debug, Clone)]
+pub struct Control {
+    pub subscription: Subscription,
+    /// A paused worker stops polling but keeps its cache,
+    /// so it picks up every post made in between when resumed.
+    pub paused: bool,
+    /// The board's latest metadata, for workers started from the site's board list.
+    pub board: Option<Arc<Board>>,
+}
+
+/// Bounds of an adaptive refresh rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshRange {
+    pub min_ms: i64,
+    pub max_ms: i64,
 }
 
 impl Subscription {
     const DEFAULT_REFRESH_RATE_MS: i64 = 10000;
+    const DEFAULT_BUFFER: usize = 100;
+    const DEFAULT_CHECKPOINT_INTERVAL_MS: i64 = 60000;
+    const DEFAULT_SHUTDOWN_TIMEOUT_MS: i64 = 5000;
+    pub(crate) const DEFAULT_BOARDS_REFRESH_MS: i64 = 600000;
     pub fn new(name: String, refresh_rate_ms: Option<i64>) -> Subscription {
         Subscription {
             board_name: name,
             refresh_rate_ms: refresh_rate_ms.unwrap_or(Self::DEFAULT_REFRESH_RATE_MS),
+            thread_no: None,
+            track_deletions: false,
+            track_pages: false,
+            buffer: Self::DEFAULT_BUFFER,
+            backpressure: Backpressure::default(),
+            adaptive_refresh: None,
+            filter: None,
+            checkpoint_interval_ms: Self::DEFAULT_CHECKPOINT_INTERVAL_MS,
+            shutdown_timeout_ms: Self::DEFAULT_SHUTDOWN_TIMEOUT_MS,
+            poll_catalog: false,
+            all_boards_refresh_ms: None,
+            backfill: Backfill::None,
+            order: EventOrder::Arrival,
+        }
+    }
+
+    pub fn thread(board: String, no: i32, refresh_rate_ms: Option<i64>) -> Subscription {
+        Subscription {
+            thread_no: Some(no),
+            ..Self::new(board, refresh_rate_ms)
+        }
+    }
+
+    /// Every board of the site.
+    pub fn all_boards(refresh_rate_ms: Option<i64>) -> Subscription {
+        Subscription {
+            all_boards_refresh_ms: Some(Self::DEFAULT_BOARDS_REFRESH_MS),
+            ..Self::new(String::new(), refresh_rate_ms)
+        }
+    }
+
+    pub fn id(&self) -> SubscriptionId {
+        match (self.thread_no, self.all_boards_refresh_ms) {
+            (_, Some(_)) => SubscriptionId::AllBoards,
+            (Some(no), None) => SubscriptionId::Thread(self.board_name.clone(), no),
+            (None, None) => SubscriptionId::Board(self.board_name.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SubscriptionId {
+    Board(String),
+    Thread(String, i32),
+    AllBoards,
+}
+
+impl Display for SubscriptionId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Board(board) => write!(f, "/{}/", board),
+            Self::Thread(board, no) => write!(f, "/{}/{}", board, no),
+            Self::AllBoards => write!(f, "/*/"),
         }
     }
 }
 
+/// A running subscription's worker.
+struct WorkerHandle {
+    kill: tokio::sync::oneshot::Sender<()>,
+    control: tokio::sync::watch::Sender<Control>,
+    /// Resolves to the final caches of the worker's boards once it stopped.
+    task: tokio::task::JoinHandle<HashMap<String, BoardCache>>,
+    /// Tasks delivering the subscription's events to its consumers.
+    flush: Vec<tokio::task::JoinHandle<()>>,
+    shutdown_timeout: tokio::time::Duration,
+}
+
 pub struct Stream {
     boards: Arc<Vec<Board>>,
     api: Arc<Client>,
-    events_tx: tokio::sync::mpsc::Sender<crate::worker::Event>,
+    events: Arc<Broadcast>,
 
-    workers: HashMap<String, tokio::sync::oneshot::Sender<()>>,
+    workers: HashMap<SubscriptionId, WorkerHandle>,
+    statuses: HashMap<SubscriptionId, tokio::sync::watch::Receiver<WorkerStatus>>,
+    counters: HashMap<SubscriptionId, Arc<QueueCounters>>,
+    checkpoints: Option<Arc<dyn CheckpointStore>>,
+    scheduler: Option<Scheduler>,
+    restart_backoff_ms: Option<(u64, u64)>,
 }
 
 impl Stream {
+    /// `events_tx` is attached as the first consumer of the stream's events;
+    /// workers wait for it to make room rather than dropping events.
     pub fn new(
         client: Option<Arc<Client>>,
         events_tx: tokio::sync::mpsc::Sender<crate::worker::Event>,
     ) -> Self {
+        let events = Broadcast::new();
+        events.attach(events_tx);
+        Self::new_with_broadcast(client, events)
+    }
+
+    /// A stream along with the `futures::Stream` of its events.
+    /// Like the channel of `new`, it is the stream's main consumer and loses no events:
+    /// once its 100 buffered events are full, workers wait for it to catch up.
+    pub fn with_events(client: Option<Arc<Client>>) -> (Self, EventStream) {
+        let stream = Self::new_with_broadcast(client, Broadcast::new());
+        let events = stream.events.consumer_with(100, Backpressure::Block);
+        (stream, events)
+    }
+
+    fn new_with_broadcast(client: Option<Arc<Client>>, events: Broadcast) -> Self {
         Stream {
             api: client.unwrap_or_else(|| Arc::new(Client::default())),
             boards: Arc::new(Vec::new()),
-            events_tx,
+            events: Arc::new(events),
             workers: HashMap::new(),
+            statuses: HashMap::new(),
+            counters: HashMap::new(),
+            checkpoints: None,
+            scheduler: None,
+            restart_backoff_ms: None,
         }
     }
 
+    /// Share one request budget between every worker of the stream,
+    /// deciding which board poll or thread fetch goes next.
+    /// It replaces the rate limit of the client's transport for the workers' requests.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Wait `min_ms` before restarting a failed worker, doubling up to `max_ms`
+    /// while it keeps failing. (default: 1s to 60s)
+    pub fn with_restart_backoff(mut self, min_ms: u64, max_ms: u64) -> Self {
+        self.restart_backoff_ms = Some((min_ms, max_ms));
+        self
+    }
+
+    /// Persist board workers' progress to `store`, and resume from it
+    /// when subscribing, backfilling posts made while the stream was down.
+    /// Checkpoints are saved periodically and when unsubscribing.
+    pub fn with_checkpoints(mut self, store: Arc<dyn CheckpointStore>) -> Self {
+        self.checkpoints = Some(store);
+        self
+    }
+
     pub async fn subscribe(&mut self, sub: &Subscription) -> Result<(), Error> {
-        if self.workers.contains_key(&sub.board_name) {
-            return Err(Error::AlreadySubscribed(
-                "Board already subscribed".to_string(),
-            ));
+        let (events_tx, mut events_rx) = channel(sub.buffer, sub.backpressure.clone());
+        self.start(sub, events_tx).await?;
+        let events = self.events.clone();
+        let pump = tokio::spawn(async move {
+            while let Some(new_event) = events_rx.recv().await {
+                events.send(new_event).await;
+            }
+        });
+        if let Some(worker) = self.workers.get_mut(&sub.id()) {
+            worker.flush.push(pump);
         }
-        let (events_tx, mut events_rx) = tokio::sync::mpsc::channel(100);
-        let board_name = sub.board_name.clone();
-        let board_data = self.get_board_data(&board_name).await?;
-        let kill_worker = Self::start_worker(self.api.clone(), sub.clone(), board_data, events_tx);
-        let events_tx = self.events_tx.clone();
-        self.workers.insert(board_name, kill_worker);
-        tokio::spawn(async move {
+        Ok(())
+    }
+
+    /// A new consumer of the events of every `subscribe`d board,
+    /// with its own buffer of `buffer` events, skipping new events when full.
+    /// Unlike the main consumer, an extra one that falls behind doesn't hold up the
+    /// workers, and with them every other consumer; see `consumer_with` to choose.
+    /// Dropping it detaches it; the workers keep running.
+    pub fn consumer(&self, buffer: usize) -> EventStream {
+        self.events.consumer(buffer)
+    }
+
+    pub fn consumer_with(&self, buffer: usize, policy: Backpressure) -> EventStream {
+        self.events.consumer_with(buffer, policy)
+    }
+
+    /// Subscribe with a dedicated event stream.
+    /// Events of this subscription go only to the returned stream,
+    /// not to the stream's shared events channel.
+    pub async fn subscribe_stream(&mut self, sub: &Subscription) -> Result<EventStream, Error> {
+        let (events_tx, events) = channel(sub.buffer, sub.backpressure.clone());
+        self.start(sub, events_tx).await?;
+        Ok(events)
+    }
+
+    /// The state of every subscription: whether its worker is running or
+    /// restarting after a failure, its last successful poll, error count and queue depth.
+    pub fn status(&self) -> HashMap<SubscriptionId, WorkerStatus> {
+        self.statuses
+            .keys()
+            .filter_map(|id| Some((id.clone(), self.subscription_status(id)?)))
+            .collect()
+    }
+
+    /// The last status reported by a subscription's worker.
+    pub fn subscription_status(&self, id: &SubscriptionId) -> Option<WorkerStatus> {
+        let mut status = self.statuses.get(id)?.borrow().clone();
+        if let Some(counters) = self.counters.get(id) {
+            status.queue_depth = counters.depth();
+        }
+        Some(status)
+    }
+
+    /// Counters of a subscription's event buffer, e.g. how many events were dropped.
+    pub fn counters(&self, id: &SubscriptionId) -> Option<Arc<QueueCounters>> {
+        self.counters.get(id).cloned()
+    }
+
+    /// Start the worker, queueing its events into `events` with the
+    /// subscription's backpressure policy.
+    /// With any policy but `Block` a slow consumer never stalls the worker.
+    async fn start(&mut self, sub: &Subscription, events: EventSender) -> Result<(), Error> {
+        self.remove_finished();
+        let id = sub.id();
+        if self.workers.contains_key(&id) {
+            return Err(Error::AlreadySubscribed(id.to_string()));
+        }
+        let board_data = match sub.all_boards_refresh_ms {
+            Some(_) => None,
+            None => Some(self.get_board_data(&sub.board_name).await?),
+        };
+        let (events_tx, mut events_rx) = tokio::sync::mpsc::channel(sub.buffer.max(1));
+        let (control, control_rx) = tokio::sync::watch::channel(Control {
+            subscription: sub.clone(),
+            paused: false,
+            board: None,
+        });
+        let (kill, task, status) = match (sub.thread_no, board_data) {
+            (_, None) => self.spawn_supervised(
+                Self::start_site_worker(
+                    self.api.clone(),
+                    sub.clone(),
+                    events_tx,
+                    control_rx,
+                    self.checkpoints.clone(),
+                    self.scheduler.clone(),
+                    self.restart_backoff_ms,
+                ),
+                SiteWorker::into_caches,
+            ),
+            (Some(no), Some(board_data)) => self.spawn_supervised(
+                Self::start_thread_worker(
+                    self.api.clone(),
+                    sub.clone(),
+                    board_data,
+                    no,
+                    events_tx,
+                    control_rx,
+                    self.scheduler.clone(),
+                ),
+                |_| HashMap::new(),
+            ),
+            (None, Some(board_data)) => self.spawn_supervised(
+                Self::start_worker(
+                    self.api.clone(),
+                    sub.clone(),
+                    board_data,
+                    events_tx,
+                    control_rx,
+                    self.checkpoints.clone(),
+                    self.scheduler.clone(),
+                ),
+                |worker| {
+                    let board = worker.board().name().to_string();
+                    HashMap::from([(board, worker.into_cache())])
+                },
+            ),
+        };
+        self.counters.insert(id.clone(), events.counters());
+        self.statuses.insert(id.clone(), status);
+        let forwarder = tokio::spawn(async move {
             while let Some(new_event) = events_rx.recv().await {
-                events_tx.send(new_event).await.unwrap();
+                if events.send(new_event).await.is_err() {
+                    debug!("Event consumer dropped");
+                    break;
+                }
             }
         });
+        self.workers.insert(
+            id,
+            WorkerHandle {
+                kill,
+                control,
+                task,
+                flush: vec![forwarder],
+                shutdown_timeout: tokio::time::Duration::from_millis(
+                    sub.shutdown_timeout_ms.max(0) as u64,
+                ),
+            },
+        );
+        Ok(())
+    }
+
+    /// Change the settings of a running subscription, keeping its worker and cache.
+    /// They apply right away: a sleeping worker polls again with the new settings.
+    /// `buffer` and `backpressure` are fixed when subscribing and can't be changed.
+    pub fn update_subscription(&self, sub: &Subscription) -> Result<(), Error> {
+        self.control(&sub.id(), |control| control.subscription = sub.clone())
+    }
+
+    /// Stop polling a subscription. Its worker keeps its cache, and sends
+    /// everything it missed when resumed.
+    pub fn pause(&self, id: &SubscriptionId) -> Result<(), Error> {
+        self.control(id, |control| control.paused = true)
+    }
+
+    pub fn resume(&self, id: &SubscriptionId) -> Result<(), Error> {
+        self.control(id, |control| control.paused = false)
+    }
+
+    fn control(&self, id: &SubscriptionId, modify: impl FnOnce(&mut Control)) -> Result<(), Error> {
+        let worker = self
+            .workers
+            .get(id)
+            .ok_or_else(|| Error::NotSubscribed(id.to_string()))?;
+        worker.control.send_modify(modify);
         Ok(())
     }
 
     pub fn unsubscribe(&mut self, board: &str) {
-        self.kill_worker(board);
+        self.kill_worker(&SubscriptionId::Board(board.to_string()));
+    }
+
+    pub fn unsubscribe_thread(&mut self, board: &str, no: i32) {
+        self.kill_worker(&SubscriptionId::Thread(board.to_string(), no));
     }
 
     async fn get_board_data(&mut self, board: &str) -> Result<Board, Error> {
@@ -76,34 +449,185 @@ impl Stream {
         }
     }
 
-    pub fn kill_worker(&mut self, board: &str) {
-        if let Some(worker) = self.workers.remove(board) {
-            let _ = worker.send(());
+    /// Forget subscriptions whose worker is done, such as thread watches that ended,
+    /// so they can be subscribed to again.
+    fn remove_finished(&mut self) {
+        let finished = self
+            .workers
+            .iter()
+            .filter(|(_, worker)| worker.task.is_finished())
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for id in finished {
+            debug!("Subscription {} is done", id);
+            self.counters.remove(&id);
+            self.statuses.remove(&id);
+            self.workers.remove(&id);
         }
     }
 
-    fn start_worker(
+    pub fn kill_worker(&mut self, id: &SubscriptionId) {
+        self.counters.remove(id);
+        self.statuses.remove(id);
+        if let Some(worker) = self.workers.remove(id) {
+            let _ = worker.kill.send(());
+        }
+    }
+
+    /// Stop every subscription, waking sleeping workers right away.
+    /// In-flight fetches get the subscription's `shutdown_timeout_ms` to finish,
+    /// and so do its pending events to reach the consumers.
+    /// Workers that haven't stopped after twice that are aborted.
+    /// Returns the final cache of every board, e.g. for a last checkpoint.
+    pub async fn shutdown(&mut self) -> HashMap<String, BoardCache> {
+        self.counters.clear();
+        self.statuses.clear();
+        let workers = self.workers.drain().collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for (id, worker) in workers {
+            let _ = worker.kill.send(());
+            tasks.push((id, worker.task, worker.flush, worker.shutdown_timeout));
+        }
+        let mut caches = HashMap::new();
+        for (id, mut task, flush, shutdown_timeout) in tasks {
+            // In-flight fetches get `shutdown_timeout`, and stopping the worker as long again.
+            match clock::timeout(self.api.clock().as_ref(), shutdown_timeout * 2, &mut task).await {
+                Some(Ok(worker_caches)) => caches.extend(worker_caches),
+                Some(Err(e)) => error!("Error stopping worker {}: {:?}", id, e),
+                None => {
+                    error!("Timed out stopping worker {}", id);
+                    task.abort();
+                }
+            }
+            for mut events in flush {
+                if clock::timeout(self.api.clock().as_ref(), shutdown_timeout, &mut events)
+                    .await
+                    .is_none()
+                {
+                    error!("Timed out flushing events of {}", id);
+                    events.abort();
+                }
+            }
+        }
+        caches
+    }
+
+    pub(crate) fn start_worker(
         api: Arc<Client>,
         cfg: Subscription,
         board: Board,
         new_posts_tx: tokio::sync::mpsc::Sender<crate::worker::Event>,
-    ) -> tokio::sync::oneshot::Sender<()> {
+        control: tokio::sync::watch::Receiver<Control>,
+        checkpoints: Option<Arc<dyn CheckpointStore>>,
+        scheduler: Option<Scheduler>,
+    ) -> Supervisor<BoardWorker> {
         info!("Starting worker for board {}", board.board.clone());
-        let (kill_tx, kill_rx) = tokio::sync::oneshot::channel();
-        tokio::spawn(async move {
-            if let Err(e) = crate::worker::BoardWorker::new_and_run(
+        let events_tx = new_posts_tx.clone();
+        let clock = api.clock().clone();
+        let restart = MemoryCheckpointStore::new();
+        Supervisor::new(cfg.id(), events_tx, move || {
+            let mut worker = BoardWorker::new(
                 api.clone(),
-                cfg,
-                board,
-                new_posts_tx,
-                Some(kill_rx),
+                cfg.clone(),
+                board.clone(),
+                new_posts_tx.clone(),
+                None,
             )
-            .await
-            {
-                error!("Error in worker: {:?}", e);
+            .with_control(control.clone())
+            .with_restart_checkpoint(restart.clone());
+            if let Some(store) = checkpoints.clone() {
+                worker = worker.with_checkpoints(store);
+            }
+            if let Some(scheduler) = scheduler.clone() {
+                worker = worker.with_scheduler(scheduler);
             }
+            worker
+        })
+        .with_clock(clock)
+    }
+
+    fn start_thread_worker(
+        api: Arc<Client>,
+        cfg: Subscription,
+        board: Board,
+        no: i32,
+        new_posts_tx: tokio::sync::mpsc::Sender<crate::worker::Event>,
+        control: tokio::sync::watch::Receiver<Control>,
+        scheduler: Option<Scheduler>,
+    ) -> Supervisor<ThreadWorker> {
+        info!("Starting worker for thread /{}/{}", cfg.board_name, no);
+        let events_tx = new_posts_tx.clone();
+        let clock = api.clock().clone();
+        let restart = MemoryCheckpointStore::new();
+        Supervisor::new(cfg.id(), events_tx, move || {
+            let mut worker =
+                ThreadWorker::new(api.clone(), cfg.clone(), no, new_posts_tx.clone(), None)
+                    .with_board(board.clone())
+                    .with_control(control.clone())
+                    .with_restart_checkpoint(restart.clone());
+            if let Some(scheduler) = scheduler.clone() {
+                worker = worker.with_scheduler(scheduler);
+            }
+            worker
+        })
+        .with_clock(clock)
+    }
+
+    /// Watch every board, under the stream's scheduler or one of the site's own.
+    fn start_site_worker(
+        api: Arc<Client>,
+        cfg: Subscription,
+        new_posts_tx: tokio::sync::mpsc::Sender<crate::worker::Event>,
+        control: tokio::sync::watch::Receiver<Control>,
+        checkpoints: Option<Arc<dyn CheckpointStore>>,
+        scheduler: Option<Scheduler>,
+        restart_backoff_ms: Option<(u64, u64)>,
+    ) -> Supervisor<SiteWorker> {
+        info!("Starting worker for all boards");
+        let scheduler = scheduler.unwrap_or_else(|| {
+            Scheduler::with_clock(Scheduler::DEFAULT_INTERVAL_MS, api.clock().clone())
+        });
+        let events_tx = new_posts_tx.clone();
+        let clock = api.clock().clone();
+        Supervisor::new(cfg.id(), events_tx, move || {
+            let mut worker = SiteWorker::new(api.clone(), cfg.clone(), new_posts_tx.clone(), None)
+                .with_control(control.clone())
+                .with_scheduler(scheduler.clone());
+            if let Some(store) = checkpoints.clone() {
+                worker = worker.with_checkpoints(store);
+            }
+            if let Some((min_ms, max_ms)) = restart_backoff_ms {
+                worker = worker.with_restart_backoff(min_ms, max_ms);
+            }
+            worker
+        })
+        .with_clock(clock)
+    }
+
+    /// Supervise a worker until it is killed, then keep the caches `finish` takes of it.
+    fn spawn_supervised<W: Supervised>(
+        &self,
+        supervisor: Supervisor<W>,
+        finish: fn(W) -> HashMap<String, BoardCache>,
+    ) -> (
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<HashMap<String, BoardCache>>,
+        tokio::sync::watch::Receiver<WorkerStatus>,
+    ) {
+        let (kill_tx, kill_rx) = tokio::sync::oneshot::channel();
+        let supervisor = match self.restart_backoff_ms {
+            Some((min_ms, max_ms)) => supervisor.with_backoff(min_ms, max_ms),
+            None => supervisor,
+        };
+        let status = supervisor.status();
+        let task = tokio::spawn(async move {
+            supervisor
+                .run(kill_rx)
+                .await
+                .map(finish)
+                .unwrap_or_default()
         });
-        kill_tx
+        (kill_tx, task, status)
     }
 }
 
@@ -111,8 +635,305 @@ impl Stream {
 mod tests {
 
     use super::*;
+    use crate::worker::{Event, WorkerState};
+    use futures::StreamExt;
+    use rchan_api::{
+        client::Config,
+        endpoint::Endpoint,
+        response::ClientResponse,
+        transport::{ScriptedTransport, Transport, TransportFuture},
+    };
+    use rchan_types::{
+        catalog::CatalogPage,
+        post::{Post, Thread, ThreadPage},
+    };
     use tracing::debug;
 
+    /// A client serving boards whose single thread gets one new reply
+    /// after the worker starts.
+    fn scripted_client(boards: &[&str]) -> Arc<Client> {
+        client(scripted_transport(boards, 0))
+    }
+
+    fn client(transport: Arc<ScriptedTransport>) -> Arc<Client> {
+        Arc::new(Client::with_transport(
+            Some(Config::new(None, Some(0))),
+            transport,
+        ))
+    }
+
+    /// The transport of `scripted_client`, with the first `failures` board polls failing.
+    fn scripted_transport(boards: &[&str], failures: usize) -> Arc<ScriptedTransport> {
+        let transport = Arc::new(ScriptedTransport::new());
+        transport.push(
+            Endpoint::Boards,
+            ClientResponse::Boards(Arc::new(
+                boards
+                    .iter()
+                    .map(|b| Board {
+                        board: b.to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )),
+        );
+        let op = |last_modified| Post {
+            no: 1,
+            resto: Some(0),
+            last_modified: Some(last_modified),
+            ..Default::default()
+        };
+        for board in boards {
+            transport.push(
+                Endpoint::Catalog(board.to_string()),
+                ClientResponse::Catalog(Arc::new(vec![CatalogPage {
+                    page: 1,
+                    threads: vec![op(100)],
+                }])),
+            );
+            for _ in 0..failures {
+                transport.push_status(Endpoint::Threads(board.to_string()), 500);
+            }
+            transport.push(
+                Endpoint::Threads(board.to_string()),
+                ClientResponse::Threads(Arc::new(vec![ThreadPage {
+                    page: 1,
+                    threads: vec![op(110)],
+                }])),
+            );
+            transport.push(
+                Endpoint::Thread(board.to_string(), 1),
+                ClientResponse::Thread(Arc::new(Thread {
+                    posts: vec![
+                        op(110),
+                        Post {
+                            no: 2,
+                            resto: Some(1),
+                            ..Default::default()
+                        },
+                    ],
+                })),
+            );
+        }
+        transport
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_event_streams() {
+        let (mut stream, events) = Stream::with_events(Some(scripted_client(&["g", "v"])));
+        let g_events = stream
+            .subscribe_stream(&Subscription::new("g".to_string(), None))
+            .await
+            .unwrap();
+        stream
+            .subscribe(&Subscription::new("v".to_string(), None))
+            .await
+            .unwrap();
+
+        let board = |event: Event| match event {
+            Event::NewPost(p) => p.board,
+            other => panic!("Unexpected event: {:?}", other),
+        };
+        let g: Vec<String> = g_events.map(board).take(1).collect().await;
+        let v: Vec<String> = events.map(board).take(1).collect().await;
+        assert_eq!(g, vec!["g".to_string()]);
+        assert_eq!(v, vec!["v".to_string()]);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_restart_failed_worker() {
+        let (stream, mut events) = Stream::with_events(Some(client(scripted_transport(&["g"], 1))));
+        let mut stream = stream.with_restart_backoff(1, 10);
+        let sub = Subscription::new("g".to_string(), None);
+        stream.subscribe(&sub).await.unwrap();
+
+        assert!(matches!(
+            events.next().await,
+            Some(Event::WorkerFailed(failed)) if failed.attempt == 1 && failed.subscription == sub.id()
+        ));
+        assert!(matches!(
+            events.next().await,
+            Some(Event::WorkerRestarted(restarted)) if restarted.attempt == 1
+        ));
+        assert!(matches!(events.next().await, Some(Event::NewPost(_))));
+
+        let status = stream.status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[&sub.id()].errors, 1);
+        assert!(status[&sub.id()].last_error.is_some());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_shutdown() {
+        let (mut stream, mut events) = Stream::with_events(Some(scripted_client(&["g"])));
+        let sub = Subscription::new("g".to_string(), Some(600_000));
+        stream.subscribe(&sub).await.unwrap();
+        assert!(matches!(events.next().await, Some(Event::NewPost(_))));
+
+        // The worker is sleeping for 10 minutes.
+        let caches = tokio::time::timeout(tokio::time::Duration::from_secs(5), stream.shutdown())
+            .await
+            .unwrap();
+        assert_eq!(caches["g"].checkpoint("g").last_post_no, 2);
+        assert!(caches["g"].last_update_sec() > 0);
+        assert!(stream.status().is_empty());
+    }
+
+    /// Serves `ScriptedTransport`'s responses, except for thread 2, which never loads.
+    #[derive(Debug)]
+    struct HangingTransport(ScriptedTransport);
+
+    impl Transport for HangingTransport {
+        fn get<'a>(&'a self, endpoint: &'a Endpoint, https: bool) -> TransportFuture<'a> {
+            match endpoint {
+                Endpoint::Thread(_, 2) => Box::pin(futures::future::pending()),
+                _ => self.0.get(endpoint, https),
+            }
+        }
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_stuck_worker() {
+        let transport = ScriptedTransport::new();
+        transport.push(
+            Endpoint::Boards,
+            ClientResponse::Boards(Arc::new(vec![Board {
+                board: "g".to_string(),
+                ..Default::default()
+            }])),
+        );
+        let post = |no, resto| Post {
+            no,
+            resto: Some(resto),
+            last_modified: Some(100),
+            ..Default::default()
+        };
+        let bumped = |no| Post {
+            last_modified: Some(110),
+            ..post(no, 0)
+        };
+        transport.push(
+            Endpoint::Catalog("g".to_string()),
+            ClientResponse::Catalog(Arc::new(vec![CatalogPage {
+                page: 1,
+                threads: vec![post(1, 0), post(2, 0)],
+            }])),
+        );
+        transport.push(
+            Endpoint::Threads("g".to_string()),
+            ClientResponse::Threads(Arc::new(vec![ThreadPage {
+                page: 1,
+                threads: vec![bumped(1), bumped(2)],
+            }])),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            ClientResponse::Thread(Arc::new(Thread {
+                posts: std::iter::once(post(1, 0))
+                    .chain((3..=20).map(|no| post(no, 1)))
+                    .collect(),
+            })),
+        );
+        let client = Client::with_transport(
+            Some(Config::new(None, Some(0))),
+            Arc::new(HangingTransport(transport)),
+        );
+        // Nobody reads the events, so the worker blocks sending them once the queues are full.
+        let (events_tx, _events_rx) = tokio::sync::mpsc::channel(1);
+        let mut stream = Stream::new(Some(Arc::new(client)), events_tx);
+        let mut sub = Subscription::new("g".to_string(), None);
+        sub.buffer = 1;
+        sub.backpressure = Backpressure::Block;
+        sub.order = EventOrder::PostNumber;
+        sub.shutdown_timeout_ms = 1000;
+        stream.subscribe(&sub).await.unwrap();
+        let status = stream.statuses[&sub.id()].clone();
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+        // Killed while fetching thread 2, the worker gets stuck sending thread 1's sorted posts.
+        // Stopping it times out after 2s, and flushing its events 1s later.
+        let (caches, ()) = tokio::join!(stream.shutdown(), async {
+            tokio::time::sleep(tokio::time::Duration::from_millis(2500)).await;
+            // The worker's status channel closes once the worker is dropped.
+            assert!(status.has_changed().is_err());
+        });
+        assert!(caches.is_empty());
+        assert!(logs_contain("Timed out stopping worker"));
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_resubscribe_ended_watch() {
+        let (mut stream, mut events) = Stream::with_events(Some(scripted_client(&["g"])));
+        // Thread 5 isn't scripted, so it 404s.
+        let sub = Subscription::thread("g".to_string(), 5, None);
+        stream.subscribe(&sub).await.unwrap();
+        assert!(matches!(events.next().await, Some(Event::ThreadEnded(_))));
+        while !stream.workers[&sub.id()].task.is_finished() {
+            tokio::task::yield_now().await;
+        }
+
+        stream.subscribe(&sub).await.unwrap();
+        assert!(matches!(events.next().await, Some(Event::ThreadEnded(_))));
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_pause_and_update() {
+        let transport = scripted_transport(&["g"], 0);
+        let (mut stream, mut events) = Stream::with_events(Some(client(transport.clone())));
+        let sub = Subscription::new("g".to_string(), Some(10));
+        stream.subscribe(&sub).await.unwrap();
+        assert!(matches!(events.next().await, Some(Event::NewPost(_))));
+
+        stream.pause(&sub.id()).unwrap();
+        while stream.status()[&sub.id()].state != WorkerState::Paused {
+            tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+        }
+        // A new thread, made while paused.
+        let post = |no, resto, com: &str| Post {
+            no,
+            resto: Some(resto),
+            com: Some(com.to_string()),
+            last_modified: Some(120),
+            ..Default::default()
+        };
+        transport.push(
+            Endpoint::Threads("g".to_string()),
+            ClientResponse::Threads(Arc::new(vec![ThreadPage {
+                page: 1,
+                threads: vec![post(5, 0, ""), post(1, 0, "")],
+            }])),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 5),
+            ClientResponse::Thread(Arc::new(Thread {
+                posts: vec![post(5, 0, "drop"), post(6, 5, "drop"), post(7, 5, "keep")],
+            })),
+        );
+        let paused = tokio::time::timeout(tokio::time::Duration::from_millis(50), events.next());
+        assert!(paused.await.is_err());
+
+        let sub = Subscription {
+            filter: Some("comment:/keep/".parse().unwrap()),
+            ..sub
+        };
+        stream.update_subscription(&sub).unwrap();
+        stream.resume(&sub.id()).unwrap();
+        assert!(matches!(
+            events.next().await,
+            Some(Event::NewPost(event)) if event.post.no == 7
+        ));
+        assert!(matches!(
+            stream.pause(&SubscriptionId::Board("v".to_string())),
+            Err(Error::NotSubscribed(_))
+        ));
+    }
+
     #[tokio::test]
     #[tracing_test::traced_test]
     async fn test_subscribe() {
@@ -154,10 +975,27 @@ mod tests {
                         .and_modify(|e| *e += 1)
                         .or_insert(1);
                 }
+                _ => {}
             }
             if counts.values().all(|v| *v >= counts_needed) {
                 break;
             }
         }
     }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_consumers() {
+        let (mut stream, events) = Stream::with_events(Some(scripted_client(&["g"])));
+        let mut archiver = stream.consumer(10);
+        let alerts = stream.consumer(10);
+        drop(events);
+        alerts.detach();
+        stream
+            .subscribe(&Subscription::new("g".to_string(), None))
+            .await
+            .unwrap();
+        assert!(matches!(archiver.recv().await, Some(Event::NewPost(p)) if p.post.no == 2));
+        assert_eq!(archiver.lagged(), 0);
+    }
 }
@@ -6,9 +6,21 @@ pub enum Error {
     #[error("Already subscribed to board: {0}")]
     AlreadySubscribed(String),
 
+    #[error("Not subscribed: {0}")]
+    NotSubscribed(String),
+
     #[error("Board not found: {0}")]
     BoardNotFound(String),
 
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(String),
+
     #[error("Invalid Response")]
     InvalidResponse,
 }
@@ -0,0 +1,695 @@
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use futures::future::BoxFuture;
+use tokio::sync::Notify;
+use tracing::{debug, error};
+
+use super::worker::Event;
+
+/// What to do with a new event when a consumer's buffer is full.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Wait for the consumer to make room, slowing down the producer.
+    #[default]
+    Block,
+    /// Drop the oldest buffered event to make room.
+    DropOldest,
+    /// Drop the new event.
+    DropNewest,
+    /// Append events to a file once the buffer is full,
+    /// delivering them in order after the buffered ones.
+    /// Every queue spills to a file of its own next to the given path,
+    /// removed once the queue is dropped.
+    Spill(PathBuf),
+}
+
+/// Counters of a single event queue.
+#[derive(Debug, Default)]
+pub struct QueueCounters {
+    dropped: AtomicU64,
+    lagged: AtomicU64,
+    spilled: AtomicU64,
+    depth: AtomicUsize,
+}
+
+impl QueueCounters {
+    /// Events dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Events dropped since the last call.
+    pub fn lagged(&self) -> u64 {
+        self.lagged.swap(0, Ordering::Relaxed)
+    }
+
+    /// Events written to the spill file.
+    pub fn spilled(&self) -> u64 {
+        self.spilled.load(Ordering::Relaxed)
+    }
+
+    /// Events waiting to be received, buffered or spilled.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    fn drop_event(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.lagged.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+struct Spill {
+    path: PathBuf,
+    /// Events in the file not read back yet.
+    len: usize,
+    /// Offset of the first event not read back yet.
+    read_from: u64,
+}
+
+impl Spill {
+    fn new(path: &Path) -> Spill {
+        static QUEUES: AtomicU64 = AtomicU64::new(0);
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(
+            ".{}-{}",
+            std::process::id(),
+            QUEUES.fetch_add(1, Ordering::Relaxed)
+        ));
+        Spill {
+            path: path.with_file_name(name),
+            len: 0,
+            read_from: 0,
+        }
+    }
+
+    async fn push(&mut self, event: &Event) -> std::io::Result<()> {
+        let line = serde_json::to_string(event).map_err(std::io::Error::other)?;
+        let path = self.path.clone();
+        blocking(move || {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", line)
+        })
+        .await?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Read back up to `max` spilled events, emptying the file once all were read.
+    async fn read(&mut self, max: usize) -> std::io::Result<Vec<Event>> {
+        let path = self.path.clone();
+        let read_from = self.read_from;
+        let (events, read_to) = blocking(move || {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(read_from))?;
+            let mut reader = BufReader::new(file);
+            let (mut events, mut read_to) = (Vec::new(), read_from);
+            let mut line = String::new();
+            while events.len() < max {
+                line.clear();
+                match reader.read_line(&mut line)? {
+                    0 => break,
+                    n => read_to += n as u64,
+                }
+                events.push(serde_json::from_str(&line).map_err(std::io::Error::other)?);
+            }
+            Ok((events, read_to))
+        })
+        .await?;
+        if events.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "spilled events are missing",
+            ));
+        }
+        self.len = self.len.saturating_sub(events.len());
+        self.read_from = read_to;
+        if self.len == 0 {
+            self.clear().await?;
+        }
+        Ok(events)
+    }
+
+    async fn clear(&mut self) -> std::io::Result<()> {
+        self.len = 0;
+        self.read_from = 0;
+        let path = self.path.clone();
+        blocking(move || File::create(path).map(|_| ())).await
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Run blocking file I/O off the async threads.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> std::io::Result<T> + Send + 'static,
+) -> std::io::Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+#[derive(Debug)]
+struct QueueState {
+    events: VecDeque<Event>,
+    /// Events in the spill file, kept in step with `Spill::len`.
+    spilled: usize,
+    senders: usize,
+    receiver: bool,
+}
+
+impl QueueState {
+    fn depth(&self) -> usize {
+        self.events.len() + self.spilled
+    }
+}
+
+#[derive(Debug)]
+struct Queue {
+    capacity: usize,
+    policy: Backpressure,
+    state: Mutex<QueueState>,
+    /// Taken before `state` by anything touching the spill file,
+    /// so file I/O never holds up the queue's other users.
+    spill: Option<tokio::sync::Mutex<Spill>>,
+    items: Notify,
+    space: Notify,
+    counters: Arc<QueueCounters>,
+}
+
+impl Queue {
+    /// Move the next spilled events to the buffer.
+    async fn unspill(&self, spill: &tokio::sync::Mutex<Spill>) {
+        let mut spill = spill.lock().await;
+        if spill.len == 0 {
+            return;
+        }
+        let result = spill.read(self.capacity).await;
+        let failed = result.is_err();
+        {
+            let mut state = self.state.lock().unwrap();
+            match result {
+                Ok(events) => state.events.extend(events),
+                Err(e) => {
+                    error!(
+                        "Error reading spilled events from {:?}: {:?}",
+                        spill.path, e
+                    );
+                    for _ in 0..spill.len {
+                        self.counters.drop_event();
+                    }
+                    spill.len = 0;
+                }
+            }
+            state.spilled = spill.len;
+            self.counters.depth.store(state.depth(), Ordering::Relaxed);
+        }
+        // Start over with an empty file rather than reading past the bad events.
+        if failed {
+            if let Err(e) = spill.clear().await {
+                error!("Error clearing spill file {:?}: {:?}", spill.path, e);
+            }
+        }
+    }
+}
+
+/// A bounded event queue applying a `Backpressure` policy when full.
+pub fn channel(capacity: usize, policy: Backpressure) -> (EventSender, EventStream) {
+    let spill = match &policy {
+        Backpressure::Spill(path) => Some(tokio::sync::Mutex::new(Spill::new(path))),
+        _ => None,
+    };
+    let queue = Arc::new(Queue {
+        capacity: capacity.max(1),
+        policy,
+        state: Mutex::new(QueueState {
+            events: VecDeque::new(),
+            spilled: 0,
+            senders: 1,
+            receiver: true,
+        }),
+        spill,
+        items: Notify::new(),
+        space: Notify::new(),
+        counters: Arc::new(QueueCounters::default()),
+    });
+    (
+        EventSender {
+            queue: queue.clone(),
+        },
+        EventStream::new(queue),
+    )
+}
+
+/// Sending half of an event queue.
+#[derive(Debug)]
+pub struct EventSender {
+    queue: Arc<Queue>,
+}
+
+/// The consumer of the queue is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+impl EventSender {
+    pub async fn send(&self, event: Event) -> Result<(), Closed> {
+        let queue = &self.queue;
+        if let Some(spill) = &queue.spill {
+            return self.send_or_spill(spill, event).await;
+        }
+        loop {
+            let space = queue.space.notified();
+            tokio::pin!(space);
+            space.as_mut().enable();
+            {
+                let mut state = queue.state.lock().unwrap();
+                if !state.receiver {
+                    return Err(Closed);
+                }
+                if state.events.len() < queue.capacity {
+                    state.events.push_back(event);
+                    queue.counters.depth.store(state.depth(), Ordering::Relaxed);
+                    queue.items.notify_one();
+                    return Ok(());
+                }
+                match &queue.policy {
+                    Backpressure::Block => {}
+                    Backpressure::DropNewest => {
+                        queue.counters.drop_event();
+                        return Ok(());
+                    }
+                    Backpressure::DropOldest => {
+                        state.events.pop_front();
+                        state.events.push_back(event);
+                        queue.counters.drop_event();
+                        return Ok(());
+                    }
+                    Backpressure::Spill(_) => unreachable!("spilling queues have a spill file"),
+                }
+            }
+            space.await;
+        }
+    }
+
+    /// Buffer the event, or append it to the spill file if the buffer is full
+    /// or earlier events are still spilled.
+    async fn send_or_spill(
+        &self,
+        spill: &tokio::sync::Mutex<Spill>,
+        event: Event,
+    ) -> Result<(), Closed> {
+        let queue = &self.queue;
+        let mut spill = spill.lock().await;
+        {
+            let mut state = queue.state.lock().unwrap();
+            if !state.receiver {
+                return Err(Closed);
+            }
+            if state.events.len() < queue.capacity && spill.len == 0 {
+                state.events.push_back(event);
+                queue.counters.depth.store(state.depth(), Ordering::Relaxed);
+                queue.items.notify_one();
+                return Ok(());
+            }
+        }
+        let result = spill.push(&event).await;
+        let mut state = queue.state.lock().unwrap();
+        match result {
+            Ok(()) => {
+                state.spilled = spill.len;
+                queue.counters.spilled.fetch_add(1, Ordering::Relaxed);
+                queue.counters.depth.store(state.depth(), Ordering::Relaxed);
+            }
+            Err(e) => {
+                error!("Error spilling event to {:?}: {:?}", spill.path, e);
+                queue.counters.drop_event();
+            }
+        }
+        queue.items.notify_one();
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        !self.queue.state.lock().unwrap().receiver
+    }
+
+    pub fn counters(&self) -> Arc<QueueCounters> {
+        self.queue.counters.clone()
+    }
+}
+
+impl Clone for EventSender {
+    fn clone(&self) -> Self {
+        self.queue.state.lock().unwrap().senders += 1;
+        EventSender {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().senders -= 1;
+        self.queue.items.notify_one();
+    }
+}
+
+/// Events from a `Stream` as a `futures::Stream`,
+/// so they can be consumed with `StreamExt` combinators.
+/// Ends once every worker feeding it has stopped.
+/// Dropping it detaches it without affecting the workers.
+pub struct EventStream {
+    queue: Arc<Queue>,
+    next: Option<BoxFuture<'static, Option<Event>>>,
+}
+
+impl std::fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventStream")
+            .field("queue", &self.queue)
+            .finish()
+    }
+}
+
+impl EventStream {
+    fn new(queue: Arc<Queue>) -> EventStream {
+        EventStream { queue, next: None }
+    }
+
+    /// A sender and the stream of events sent through it, blocking when full.
+    pub fn channel(buffer: usize) -> (EventSender, EventStream) {
+        channel(buffer, Backpressure::Block)
+    }
+
+    pub async fn recv(&mut self) -> Option<Event> {
+        match self.next.take() {
+            Some(next) => next.await,
+            None => Self::pop(self.queue.clone()).await,
+        }
+    }
+
+    async fn pop(queue: Arc<Queue>) -> Option<Event> {
+        loop {
+            let items = queue.items.notified();
+            tokio::pin!(items);
+            items.as_mut().enable();
+            let spilled = {
+                let mut state = queue.state.lock().unwrap();
+                if let Some(event) = state.events.pop_front() {
+                    queue.counters.depth.store(state.depth(), Ordering::Relaxed);
+                    queue.space.notify_one();
+                    return Some(event);
+                }
+                if state.spilled == 0 && state.senders == 0 {
+                    return None;
+                }
+                state.spilled > 0
+            };
+            match (&queue.spill, spilled) {
+                (Some(spill), true) => queue.unspill(spill).await,
+                _ => items.await,
+            }
+        }
+    }
+
+    /// Number of events dropped because the buffer was full,
+    /// since the last call.
+    pub fn lagged(&self) -> u64 {
+        self.queue.counters.lagged()
+    }
+
+    pub fn counters(&self) -> Arc<QueueCounters> {
+        self.queue.counters.clone()
+    }
+
+    /// Stop receiving events.
+    pub fn detach(self) {}
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().receiver = false;
+        self.queue.space.notify_waiters();
+    }
+}
+
+impl futures::Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        let queue = self.queue.clone();
+        let next = self.next.get_or_insert_with(|| Box::pin(Self::pop(queue)));
+        match next.as_mut().poll(cx) {
+            Poll::Ready(event) => {
+                self.next = None;
+                Poll::Ready(event)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Fans events out to any number of consumers.
+/// Every consumer has its own buffer and `Backpressure` policy;
+/// consumers that were dropped are detached on the next send.
+#[derive(Debug, Default)]
+pub struct Broadcast {
+    consumers: Mutex<Vec<EventSender>>,
+}
+
+impl Broadcast {
+    pub fn new() -> Broadcast {
+        Broadcast::default()
+    }
+
+    /// A new consumer receiving every event sent from now on,
+    /// buffering up to `buffer` events and skipping new ones when full.
+    pub fn consumer(&self, buffer: usize) -> EventStream {
+        self.consumer_with(buffer, Backpressure::DropNewest)
+    }
+
+    pub fn consumer_with(&self, buffer: usize, policy: Backpressure) -> EventStream {
+        let (tx, events) = channel(buffer, policy);
+        self.consumers.lock().unwrap().push(tx);
+        events
+    }
+
+    /// Forward events to an existing channel, waiting for it to make room
+    /// instead of dropping events.
+    pub fn attach(&self, tx: tokio::sync::mpsc::Sender<Event>) {
+        let mut events = self.consumer_with(tx.max_capacity(), Backpressure::Block);
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                if tx.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Deliver `event` to every consumer at once,
+    /// so a consumer waiting for room doesn't hold back the others.
+    pub async fn send(&self, event: Event) {
+        let consumers = self.consumers.lock().unwrap().clone();
+        futures::future::join_all(
+            consumers
+                .iter()
+                .map(|consumer| consumer.send(event.clone())),
+        )
+        .await;
+        self.consumers.lock().unwrap().retain(|consumer| {
+            let closed = consumer.is_closed();
+            if closed {
+                debug!("Consumer dropped, detaching");
+            }
+            !closed
+        });
+    }
+
+    pub fn consumer_count(&self) -> usize {
+        self.consumers.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use rchan_types::post::Post;
+
+    fn event(no: i32) -> Event {
+        let post = Post {
+            no,
+            resto: Some(1),
+            ..Default::default()
+        };
+        Event::new("g".to_string(), Arc::new(post))
+    }
+
+    fn nos(events: Vec<Event>) -> Vec<i32> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                Event::NewPost(p) => p.post.no,
+                other => panic!("Unexpected event: {:?}", other),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_event_stream() {
+        let (tx, events) = EventStream::channel(10);
+        for no in 1..=4 {
+            tx.send(event(no)).await.unwrap();
+        }
+        drop(tx);
+        let nos = events
+            .filter_map(|event| async move {
+                match event {
+                    Event::NewPost(p) if p.post.no % 2 == 0 => Some(p.post.no),
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(nos, vec![2, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast() {
+        let broadcast = Broadcast::new();
+        let mut fast = broadcast.consumer(10);
+        let mut slow = broadcast.consumer(2);
+        let gone = broadcast.consumer(10);
+        gone.detach();
+        for no in 1..=5 {
+            broadcast.send(event(no)).await;
+        }
+        assert_eq!(broadcast.consumer_count(), 2);
+
+        assert_eq!(fast.lagged(), 0);
+        for no in 1..=5 {
+            assert!(matches!(fast.recv().await, Some(Event::NewPost(p)) if p.post.no == no));
+        }
+        assert_eq!(slow.lagged(), 3);
+        assert_eq!(slow.lagged(), 0);
+        for no in 1..=2 {
+            assert!(matches!(slow.recv().await, Some(Event::NewPost(p)) if p.post.no == no));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_stalled_consumer() {
+        let broadcast = Arc::new(Broadcast::new());
+        let mut stalled = broadcast.consumer_with(1, Backpressure::Block);
+        let mut live = broadcast.consumer_with(10, Backpressure::Block);
+        let producer = tokio::spawn({
+            let broadcast = broadcast.clone();
+            async move {
+                for no in 1..=3 {
+                    broadcast.send(event(no)).await;
+                }
+            }
+        });
+        for no in 1..=2 {
+            assert!(matches!(live.recv().await, Some(Event::NewPost(p)) if p.post.no == no));
+        }
+        assert!(!producer.is_finished());
+
+        for no in 1..=3 {
+            assert!(matches!(stalled.recv().await, Some(Event::NewPost(p)) if p.post.no == no));
+        }
+        producer.await.unwrap();
+        assert!(matches!(live.recv().await, Some(Event::NewPost(p)) if p.post.no == 3));
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let (tx, oldest) = channel(2, Backpressure::DropOldest);
+        for no in 1..=5 {
+            tx.send(event(no)).await.unwrap();
+        }
+        drop(tx);
+        assert_eq!(oldest.counters().dropped(), 3);
+        assert_eq!(nos(oldest.collect().await), vec![4, 5]);
+
+        let (tx, newest) = channel(2, Backpressure::DropNewest);
+        for no in 1..=5 {
+            tx.send(event(no)).await.unwrap();
+        }
+        drop(tx);
+        assert_eq!(newest.counters().dropped(), 3);
+        assert_eq!(nos(newest.collect().await), vec![1, 2]);
+
+        let (tx, mut blocked) = channel(2, Backpressure::Block);
+        let producer = tokio::spawn(async move {
+            for no in 1..=5 {
+                tx.send(event(no)).await.unwrap();
+            }
+        });
+        let mut received = vec![];
+        while let Some(event) = blocked.recv().await {
+            received.push(event);
+        }
+        producer.await.unwrap();
+        assert_eq!(nos(received), vec![1, 2, 3, 4, 5]);
+        assert_eq!(blocked.counters().dropped(), 0);
+
+        let (tx, events) = channel(1, Backpressure::Block);
+        drop(events);
+        assert_eq!(tx.send(event(1)).await, Err(Closed));
+    }
+
+    #[tokio::test]
+    async fn test_spill() {
+        let path = std::env::temp_dir().join(format!("rchan-spill-{}.jsonl", std::process::id()));
+        let (tx, mut events) = channel(2, Backpressure::Spill(path.clone()));
+        for no in 1..=5 {
+            tx.send(event(no)).await.unwrap();
+        }
+        assert_eq!(events.counters().spilled(), 3);
+        assert_eq!(events.counters().depth(), 5);
+        assert!(matches!(events.recv().await, Some(Event::NewPost(p)) if p.post.no == 1));
+        tx.send(event(6)).await.unwrap();
+        drop(tx);
+        assert_eq!(nos(events.collect().await), vec![2, 3, 4, 5, 6]);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_spill_shared_path() {
+        let path = std::env::temp_dir().join(format!("rchan-shared-{}.jsonl", std::process::id()));
+        let (first_tx, first) = channel(1, Backpressure::Spill(path.clone()));
+        let (second_tx, second) = channel(1, Backpressure::Spill(path.clone()));
+        for no in 1..=4 {
+            first_tx.send(event(no)).await.unwrap();
+            second_tx.send(event(no + 10)).await.unwrap();
+        }
+        let spill_file = {
+            let spill = first.queue.spill.as_ref().unwrap().lock().await;
+            assert_ne!(spill.path, path);
+            spill.path.clone()
+        };
+        assert!(spill_file.exists());
+        drop((first_tx, second_tx));
+        assert_eq!(nos(first.collect().await), vec![1, 2, 3, 4]);
+        assert_eq!(nos(second.collect().await), vec![11, 12, 13, 14]);
+        assert!(!spill_file.exists());
+    }
+}
@@ -0,0 +1,280 @@
+use std::{collections::HashSet, str::FromStr};
+
+use rchan_types::post::Post;
+use regex::{Regex, RegexBuilder};
+
+use super::{error::Error, worker::Event};
+
+/// A filter on posts, evaluated by the worker before events are sent.
+///
+/// Filters can be written as text, one rule per line, in the spirit of
+/// 4chan X filter lists. A post matches if any rule matches, and a rule
+/// matches if all of its space separated terms do. `!` negates a term,
+/// lines starting with `#` are comments.
+///
+/// ```text
+/// # rust threads, and anything quoting post 123
+/// op subject:/rust|cargo/i
+/// reply:123
+/// comment:/benchmark/ file ext:png,jpg !country:XX replies:50
+/// ```
+///
+/// Terms: `subject:`, `comment:`, `name:`, `trip:` and `filename:` take a `/regex/`
+/// (`i` flag for case insensitive), `file` (has a file), `ext:webm,mp4`,
+/// `md5:<base64>,...`, `id:<poster id>`, `country:<code or board flag>`,
+/// `capcode` or `capcode:<capcode>`, `op`, `reply:<post no>` and
+/// `replies:<minimum thread replies>`.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    All(Vec<Filter>),
+    Any(Vec<Filter>),
+    Not(Box<Filter>),
+    Subject(Regex),
+    Comment(Regex),
+    Name(Regex),
+    Trip(Regex),
+    Filename(Regex),
+    HasFile,
+    /// File extensions, without the leading dot.
+    FileType(HashSet<String>),
+    Md5(HashSet<String>),
+    PosterId(String),
+    /// Country code or board flag.
+    Country(String),
+    /// Any capcode if None.
+    Capcode(Option<String>),
+    OpOnly,
+    ReplyTo(i32),
+    MinReplies(i32),
+}
+
+impl Filter {
+    /// Whether a post matches.
+    /// `op` is the OP of the post's thread, if known, for `MinReplies`.
+    pub fn matches(&self, post: &Post, op: Option<&Post>) -> bool {
+        let is_match = |re: &Regex, text: Option<String>| text.is_some_and(|t| re.is_match(&t));
+        match self {
+            Filter::All(filters) => filters.iter().all(|f| f.matches(post, op)),
+            Filter::Any(filters) => filters.iter().any(|f| f.matches(post, op)),
+            Filter::Not(filter) => !filter.matches(post, op),
+            Filter::Subject(re) => is_match(re, post.clean_title()),
+            Filter::Comment(re) => is_match(re, post.clean_comment()),
+            Filter::Name(re) => is_match(re, post.name.clone()),
+            Filter::Trip(re) => is_match(re, post.trip.clone()),
+            Filter::Filename(re) => is_match(re, post.filename.clone()),
+            Filter::HasFile => post.has_image(),
+            Filter::FileType(exts) => post
+                .ext
+                .as_ref()
+                .is_some_and(|ext| exts.contains(&ext.trim_start_matches('.').to_lowercase())),
+            Filter::Md5(hashes) => post.md5.as_ref().is_some_and(|md5| hashes.contains(md5)),
+            Filter::PosterId(id) => post.id.as_ref() == Some(id),
+            Filter::Country(country) => [&post.country, &post.board_flag]
+                .iter()
+                .copied()
+                .flatten()
+                .any(|c| c.eq_ignore_ascii_case(country)),
+            Filter::Capcode(None) => post.capcode.is_some(),
+            Filter::Capcode(Some(capcode)) => post
+                .capcode
+                .as_ref()
+                .is_some_and(|c| c.eq_ignore_ascii_case(capcode)),
+            Filter::OpOnly => post.is_op(),
+            Filter::ReplyTo(no) => post.quotes().contains(no),
+            Filter::MinReplies(min) => op
+                .unwrap_or(post)
+                .replies
+                .is_some_and(|replies| replies >= *min),
+        }
+    }
+
+    /// Whether an event should be sent.
+    /// Events about whole threads, boards or workers, rather than posts, always are.
+    pub fn matches_event(&self, event: &Event, op: Option<&Post>) -> bool {
+        match event {
+            Event::NewPost(e) => self.matches(&e.post, op),
+            Event::NewThread(e) => self.matches(&e.post, op),
+            Event::PostDeleted(e) => self.matches(&e.post, op),
+            Event::FileDeleted(e) => self.matches(&e.post, op),
+            Event::ThreadEnded(_)
+            | Event::ThreadChanged(_)
+            | Event::WorkerFailed(_)
+            | Event::WorkerRestarted(_)
+            | Event::BoardMetadataChanged(_) => true,
+        }
+    }
+
+    fn parse_rule(line: &str) -> Result<Filter, Error> {
+        let mut terms = vec![];
+        let mut rest = line.trim();
+        while !rest.is_empty() {
+            let (term, tail) = split_term(rest)?;
+            terms.push(Self::parse_term(term)?);
+            rest = tail.trim_start();
+        }
+        Ok(match terms.len() {
+            1 => terms.remove(0),
+            _ => Filter::All(terms),
+        })
+    }
+
+    fn parse_term(term: &str) -> Result<Filter, Error> {
+        if let Some(term) = term.strip_prefix('!') {
+            return Ok(Filter::Not(Box::new(Self::parse_term(term)?)));
+        }
+        let (key, value) = match term.split_once(':') {
+            Some((key, value)) => (key, Some(value)),
+            None => (term, None),
+        };
+        let invalid = || Error::InvalidFilter(format!("Invalid term: {}", term));
+        let list = |value: &str| {
+            value
+                .split(',')
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_string())
+                .collect::<HashSet<_>>()
+        };
+        Ok(match (key, value) {
+            ("subject", Some(value)) => Filter::Subject(parse_regex(value)?),
+            ("comment", Some(value)) => Filter::Comment(parse_regex(value)?),
+            ("name", Some(value)) => Filter::Name(parse_regex(value)?),
+            ("trip", Some(value)) => Filter::Trip(parse_regex(value)?),
+            ("filename", Some(value)) => Filter::Filename(parse_regex(value)?),
+            ("file", None) => Filter::HasFile,
+            ("ext", Some(value)) => Filter::FileType(
+                list(value)
+                    .into_iter()
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .collect(),
+            ),
+            ("md5", Some(value)) => Filter::Md5(list(value)),
+            ("id", Some(value)) => Filter::PosterId(value.to_string()),
+            ("country", Some(value)) => Filter::Country(value.to_string()),
+            ("capcode", value) => Filter::Capcode(value.map(|v| v.to_string())),
+            ("op", None) => Filter::OpOnly,
+            ("reply", Some(value)) => Filter::ReplyTo(value.parse().map_err(|_| invalid())?),
+            ("replies", Some(value)) => Filter::MinReplies(value.parse().map_err(|_| invalid())?),
+            _ => return Err(invalid()),
+        })
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Filter::parse_rule)
+            .collect::<Result<Vec<_>, _>>()?;
+        match rules.len() {
+            0 => Err(Error::InvalidFilter("Empty filter".to_string())),
+            1 => Ok(rules.remove(0)),
+            _ => Ok(Filter::Any(rules)),
+        }
+    }
+}
+
+/// Split off the first term, keeping spaces inside a `/regex/`.
+fn split_term(s: &str) -> Result<(&str, &str), Error> {
+    let regex_start = s
+        .find(":/")
+        .filter(|i| !s[..*i].contains(char::is_whitespace));
+    let end = match regex_start {
+        Some(start) => {
+            let body = start + 2;
+            let mut escaped = false;
+            let close = s[body..]
+                .char_indices()
+                .find(|(_, c)| {
+                    let close = *c == '/' && !escaped;
+                    escaped = *c == '\\' && !escaped;
+                    close
+                })
+                .map(|(i, _)| body + i)
+                .ok_or_else(|| Error::InvalidFilter(format!("Unclosed regex: {}", s)))?;
+            s[close..]
+                .find(char::is_whitespace)
+                .map_or(s.len(), |i| close + i)
+        }
+        None => s.find(char::is_whitespace).unwrap_or(s.len()),
+    };
+    Ok(s.split_at(end))
+}
+
+/// Parse `/pattern/flags`.
+fn parse_regex(value: &str) -> Result<Regex, Error> {
+    let invalid = |e: String| Error::InvalidFilter(format!("Invalid regex {}: {}", value, e));
+    let (pattern, flags) = value
+        .strip_prefix('/')
+        .and_then(|v| v.rsplit_once('/'))
+        .ok_or_else(|| invalid("expected /pattern/".to_string()))?;
+    let mut builder = RegexBuilder::new(pattern);
+    for flag in flags.chars() {
+        match flag {
+            'i' => builder.case_insensitive(true),
+            _ => return Err(invalid(format!("unknown flag {}", flag))),
+        };
+    }
+    builder.build().map_err(|e| invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(no: i32, resto: i32) -> Post {
+        Post {
+            no,
+            resto: Some(resto),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_filter_terms() {
+        let reply = Post {
+            com: Some(
+                "<a href=\"#p1\" class=\"quotelink\">&gt;&gt;1</a><br>Rust is fast".to_string(),
+            ),
+            tim: Some(1),
+            ext: Some(".PNG".to_string()),
+            md5: Some("abc==".to_string()),
+            country: Some("NL".to_string()),
+            ..post(2, 1)
+        };
+        let op = Post {
+            sub: Some("Rust General".to_string()),
+            replies: Some(120),
+            capcode: Some("mod".to_string()),
+            ..post(1, 0)
+        };
+        let matches =
+            |filter: &str, post: &Post| filter.parse::<Filter>().unwrap().matches(post, Some(&op));
+
+        assert!(matches("comment:/rust is/i", &reply));
+        assert!(!matches("comment:/rust is/", &reply));
+        assert!(matches("file ext:png,jpg md5:abc==", &reply));
+        assert!(matches("country:nl reply:1 replies:100", &reply));
+        assert!(!matches("replies:200", &reply));
+        assert!(matches("op subject:/general/i capcode", &op));
+        assert!(!matches("op", &reply));
+        assert!(matches("!capcode:admin", &op));
+        assert!(matches("# comment\nop\ncountry:NL", &reply));
+    }
+
+    #[test]
+    fn test_invalid_filters() {
+        for filter in [
+            "",
+            "color:red",
+            "comment:/unclosed",
+            "comment:/x/g",
+            "reply:x",
+        ] {
+            assert!(filter.parse::<Filter>().is_err(), "{}", filter);
+        }
+    }
+}
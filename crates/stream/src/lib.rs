@@ -1,3 +1,10 @@
 pub mod client;
 pub mod worker;
+pub mod thread_worker;
 pub mod error;
+pub mod events;
+pub mod checkpoint;
+pub mod filter;
+pub mod scheduler;
+pub mod supervisor;
+pub mod site;
@@ -0,0 +1,321 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use rchan_api::{
+    client::{Client, GateFuture, RequestGate},
+    clock::{self, Clock},
+    endpoint::Endpoint,
+};
+use tokio::sync::Notify;
+use tracing::debug;
+
+use super::client::SubscriptionId;
+
+/// A request a worker wants to make.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    /// Poll the board's threads.json or catalog, or fetch its archive or index pages.
+    BoardPoll,
+    /// Fetch a modified thread.
+    ThreadFetch(i32),
+    /// Refresh the list of boards.
+    BoardList,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    id: SubscriptionId,
+    kind: RequestKind,
+    enqueued_ms: i64,
+    activity: u32,
+    tx: tokio::sync::oneshot::Sender<()>,
+}
+
+impl Waiter {
+    /// Stale requests of active subscriptions go first.
+    fn score(&self, now_ms: i64) -> i64 {
+        (now_ms - self.enqueued_ms).max(1) * (1 + self.activity as i64)
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    waiting: Vec<Waiter>,
+    /// Sequence number of the last grant per subscription.
+    last_granted: HashMap<SubscriptionId, u64>,
+    grants: u64,
+    running: bool,
+}
+
+impl State {
+    /// Every other grant goes to the subscription that was served least recently,
+    /// so each of N waiting subscriptions gets at least 1/2N of the budget.
+    /// The rest goes to the request with the highest staleness times activity.
+    fn pick(&mut self, now_ms: i64) -> Option<Waiter> {
+        let index = if self.grants.is_multiple_of(2) {
+            self.waiting
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, w)| {
+                    (
+                        self.last_granted.get(&w.id).copied().unwrap_or(0),
+                        w.enqueued_ms,
+                    )
+                })
+                .map(|(i, _)| i)?
+        } else {
+            self.waiting
+                .iter()
+                .enumerate()
+                .max_by_key(|(i, w)| (w.score(now_ms), Reverse(w.enqueued_ms), Reverse(*i)))
+                .map(|(i, _)| i)?
+        };
+        let waiter = self.waiting.remove(index);
+        self.grants += 1;
+        self.last_granted.insert(waiter.id.clone(), self.grants);
+        Some(waiter)
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    interval_ms: u64,
+    state: Mutex<State>,
+    waiting: Notify,
+    clock: Arc<dyn Clock>,
+}
+
+/// Owns the request budget shared by every worker of a stream,
+/// and decides which board poll or thread fetch goes next.
+/// Workers wait for a grant before each request, retries included; one is given out
+/// every `interval_ms`. Their requests skip the transport's own rate limit.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_INTERVAL_MS)
+    }
+}
+
+impl Scheduler {
+    /// The API allows one request per second.
+    pub const DEFAULT_INTERVAL_MS: u64 = 1000;
+
+    pub fn new(interval_ms: u64) -> Scheduler {
+        Self::with_clock(interval_ms, clock::system())
+    }
+
+    /// A scheduler spacing out grants, and measuring how long requests waited, by `clock`'s time.
+    pub fn with_clock(interval_ms: u64, clock: Arc<dyn Clock>) -> Scheduler {
+        Scheduler {
+            inner: Arc::new(Inner {
+                interval_ms,
+                state: Mutex::new(State::default()),
+                waiting: Notify::new(),
+                clock,
+            }),
+        }
+    }
+
+    /// Wait for the turn of a request.
+    /// `activity` is how busy the subscription is, e.g. threads modified in its last poll.
+    pub async fn acquire(&self, id: &SubscriptionId, kind: RequestKind, activity: u32) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            state.waiting.push(Waiter {
+                id: id.clone(),
+                kind,
+                enqueued_ms: self.inner.clock.now_ms(),
+                activity,
+                tx,
+            });
+            if !state.running {
+                state.running = true;
+                tokio::spawn(Self::dispatch(Arc::downgrade(&self.inner)));
+            }
+        }
+        self.inner.waiting.notify_one();
+        let _ = rx.await;
+    }
+
+    /// Requests waiting for their turn.
+    pub fn pending(&self) -> usize {
+        self.inner.state.lock().unwrap().waiting.len()
+    }
+
+    /// Grant one request per interval, until the scheduler is dropped.
+    async fn dispatch(inner: Weak<Inner>) {
+        loop {
+            let Some(inner) = inner.upgrade() else {
+                return;
+            };
+            let interval = tokio::time::Duration::from_millis(inner.interval_ms);
+            let granted = {
+                let waiting = inner.waiting.notified();
+                tokio::pin!(waiting);
+                waiting.as_mut().enable();
+                let granted = inner.grant_next();
+                if !granted {
+                    // Wake up now and then to notice the scheduler was dropped.
+                    let idle = interval.max(IDLE_CHECK);
+                    let _ = clock::timeout(inner.clock.as_ref(), idle, waiting).await;
+                }
+                granted
+            };
+            let clock = inner.clock.clone();
+            drop(inner);
+            if granted {
+                clock.sleep(interval).await;
+            }
+        }
+    }
+}
+
+impl Inner {
+    /// Grant the next request whose worker is still waiting.
+    fn grant_next(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now_ms = self.clock.now_ms();
+        while let Some(waiter) = state.pick(now_ms) {
+            debug!("Granting {:?} to {}", waiter.kind, waiter.id);
+            if waiter.tx.send(()).is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl RequestKind {
+    /// The kind of request fetching `endpoint` is.
+    fn of(endpoint: &Endpoint) -> RequestKind {
+        match endpoint {
+            Endpoint::Boards => RequestKind::BoardList,
+            Endpoint::Thread(_, no) => RequestKind::ThreadFetch(*no),
+            Endpoint::Threads(_)
+            | Endpoint::Catalog(_)
+            | Endpoint::Archive(_)
+            | Endpoint::Index(_, _) => RequestKind::BoardPoll,
+        }
+    }
+}
+
+/// The scheduler's grants for one subscription, as the gate of its worker's client:
+/// every request, retries included, waits for its turn.
+#[derive(Debug)]
+pub(crate) struct Grants {
+    scheduler: Scheduler,
+    id: SubscriptionId,
+    /// How busy the subscription is, see `Scheduler::acquire`.
+    activity: Arc<AtomicU32>,
+}
+
+impl Grants {
+    pub(crate) fn new(scheduler: Scheduler, id: SubscriptionId, activity: Arc<AtomicU32>) -> Self {
+        Grants {
+            scheduler,
+            id,
+            activity,
+        }
+    }
+
+    /// A copy of `api` waiting for these grants, instead of its transport's rate limit.
+    pub(crate) fn gate(self, api: &Client) -> Arc<Client> {
+        Arc::new(api.clone().with_gate(Arc::new(self)))
+    }
+}
+
+impl RequestGate for Grants {
+    fn wait<'a>(&'a self, endpoint: &'a Endpoint) -> GateFuture<'a> {
+        let activity = self.activity.load(Ordering::Relaxed);
+        Box::pin(
+            self.scheduler
+                .acquire(&self.id, RequestKind::of(endpoint), activity),
+        )
+    }
+}
+
+const IDLE_CHECK: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rchan_api::{client::Config, response::ClientResponse, transport::ScriptedTransport};
+
+    fn waiter(board: &str, kind: RequestKind, enqueued_ms: i64, activity: u32) -> Waiter {
+        Waiter {
+            id: SubscriptionId::Board(board.to_string()),
+            kind,
+            enqueued_ms,
+            activity,
+            tx: tokio::sync::oneshot::channel().0,
+        }
+    }
+
+    #[test]
+    fn test_pick() {
+        let mut state = State::default();
+        for no in 1..=4 {
+            state
+                .waiting
+                .push(waiter("b", RequestKind::ThreadFetch(no), 0, 10));
+        }
+        state
+            .waiting
+            .push(waiter("g", RequestKind::BoardPoll, 0, 0));
+        state
+            .waiting
+            .push(waiter("v", RequestKind::BoardPoll, 500, 0));
+
+        let order = std::iter::from_fn(|| state.pick(1000))
+            .map(|w| (w.id.to_string(), w.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            vec![
+                ("/b/".to_string(), RequestKind::ThreadFetch(1)),
+                ("/b/".to_string(), RequestKind::ThreadFetch(2)),
+                ("/g/".to_string(), RequestKind::BoardPoll),
+                ("/b/".to_string(), RequestKind::ThreadFetch(3)),
+                ("/v/".to_string(), RequestKind::BoardPoll),
+                ("/b/".to_string(), RequestKind::ThreadFetch(4)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_acquire() {
+        let scheduler = Scheduler::new(1);
+        let id = SubscriptionId::Board("g".to_string());
+        let requests = (1..=3).map(|no| scheduler.acquire(&id, RequestKind::ThreadFetch(no), 0));
+        futures::future::join_all(requests).await;
+        assert_eq!(scheduler.pending(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_grant_per_attempt() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let endpoint = Endpoint::Threads("g".to_string());
+        transport.push_status(endpoint.clone(), 500);
+        transport.push_status(endpoint.clone(), 500);
+        transport.push(endpoint, ClientResponse::Threads(Arc::new(vec![])));
+        let api = Client::with_transport(Some(Config::new(None, Some(5))), transport);
+        let id = SubscriptionId::Board("g".to_string());
+        let api = Grants::new(Scheduler::new(10_000), id, Arc::default()).gate(&api);
+
+        let start = tokio::time::Instant::now();
+        api.get_threads("g").await.unwrap();
+        // Both retries waited for a grant of their own.
+        assert!(start.elapsed() >= tokio::time::Duration::from_secs(20));
+    }
+}
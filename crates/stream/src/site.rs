@@ -0,0 +1,451 @@
+use std::{collections::HashMap, sync::Arc};
+
+use rchan_api::{client::Client, clock, error::Error};
+use rchan_types::board::Board;
+use tracing::{debug, error, info};
+
+use super::{
+    checkpoint::CheckpointStore,
+    client::{Control, Stream, Subscription},
+    scheduler::{Grants, Scheduler},
+    worker::{
+        control_changed, until_killed, BoardCache, BoardMetadataChanged, BoardWorker, Event,
+        WorkerState, WorkerStatus,
+    },
+};
+
+/// A board worker started by a `SiteWorker`.
+struct SiteBoard {
+    kill: tokio::sync::oneshot::Sender<()>,
+    control: tokio::sync::watch::Sender<Control>,
+    task: tokio::task::JoinHandle<Option<BoardWorker>>,
+}
+
+/// Watches every board of the site, following the board list.
+/// boards.json is fetched every `all_boards_refresh_ms`: a supervised board worker
+/// is started for each new board and stopped when the board is retired.
+/// Board workers get every change to their board's metadata, and
+/// `BoardMetadataChanged` is sent when a board's limits or cooldowns change.
+/// Board workers share the scheduler's request budget, along with the board list polls.
+pub struct SiteWorker {
+    api: Arc<Client>,
+    cfg: Subscription,
+    boards: HashMap<String, Board>,
+    workers: HashMap<String, SiteBoard>,
+    /// Final caches of the board workers, once stopped.
+    caches: HashMap<String, BoardCache>,
+    events_chan: tokio::sync::mpsc::Sender<Event>,
+    pub(crate) kill: Option<tokio::sync::oneshot::Receiver<()>>,
+    control: Option<tokio::sync::watch::Receiver<Control>>,
+    paused: bool,
+    pub(crate) status: Arc<tokio::sync::watch::Sender<WorkerStatus>>,
+    checkpoints: Option<Arc<dyn CheckpointStore>>,
+    scheduler: Scheduler,
+    restart_backoff_ms: Option<(u64, u64)>,
+}
+
+impl SiteWorker {
+    pub fn new(
+        api: Arc<Client>,
+        cfg: Subscription,
+        new_posts_tx: tokio::sync::mpsc::Sender<Event>,
+        kill: Option<tokio::sync::oneshot::Receiver<()>>,
+    ) -> SiteWorker {
+        let (status, _) = tokio::sync::watch::channel(WorkerStatus::default());
+        let scheduler = Scheduler::with_clock(Scheduler::DEFAULT_INTERVAL_MS, api.clock().clone());
+        SiteWorker {
+            api,
+            cfg,
+            boards: HashMap::new(),
+            workers: HashMap::new(),
+            caches: HashMap::new(),
+            events_chan: new_posts_tx,
+            kill,
+            control: None,
+            paused: false,
+            status: Arc::new(status),
+            checkpoints: None,
+            scheduler,
+            restart_backoff_ms: None,
+        }
+    }
+
+    /// Share `scheduler`'s budget instead of a budget of the site's own.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> SiteWorker {
+        self.scheduler = scheduler;
+        self
+    }
+
+    pub fn with_checkpoints(mut self, store: Arc<dyn CheckpointStore>) -> SiteWorker {
+        self.checkpoints = Some(store);
+        self
+    }
+
+    /// How long board workers wait before restarting after a failure.
+    pub fn with_restart_backoff(mut self, min_ms: u64, max_ms: u64) -> SiteWorker {
+        self.restart_backoff_ms = Some((min_ms, max_ms));
+        self
+    }
+
+    /// Take new settings and pause or resume on changes to `control`,
+    /// passing them on to every board worker.
+    pub fn with_control(
+        mut self,
+        mut control: tokio::sync::watch::Receiver<Control>,
+    ) -> SiteWorker {
+        let current = control.borrow_and_update().clone();
+        self.cfg = current.subscription;
+        self.paused = current.paused;
+        self.control = Some(control);
+        self
+    }
+
+    /// Status updates, sent after every board list refresh.
+    /// `threads` is the number of boards watched.
+    pub fn status(&self) -> tokio::sync::watch::Receiver<WorkerStatus> {
+        self.status.subscribe()
+    }
+
+    /// The boards currently watched.
+    pub fn boards(&self) -> impl Iterator<Item = &Board> {
+        self.boards.values()
+    }
+
+    /// Final caches of the board workers, by board, once the site worker stopped.
+    pub fn into_caches(self) -> HashMap<String, BoardCache> {
+        self.caches
+    }
+
+    /// Run until killed, or until the board list can't be fetched.
+    /// Running it again after a failure keeps the board workers already started.
+    pub async fn run(&mut self) -> Result<(), Error> {
+        loop {
+            self.apply_control();
+            if let Err(e) = self.refresh_boards().await {
+                error!("Error refreshing boards: {:?}", e);
+                return Err(e);
+            }
+            let refresh = tokio::time::Duration::from_millis(self.refresh_ms());
+            let clock = self.api.clock().as_ref();
+            let sleep = control_changed(&mut self.control, clock, refresh);
+            if until_killed(&mut self.kill, clock, tokio::time::Duration::ZERO, sleep)
+                .await
+                .1
+            {
+                info!("Received kill signal, stopping site worker");
+                self.stop().await;
+                return Ok(());
+            }
+        }
+    }
+
+    fn refresh_ms(&self) -> u64 {
+        self.cfg
+            .all_boards_refresh_ms
+            .unwrap_or(Subscription::DEFAULT_BOARDS_REFRESH_MS)
+            .max(0) as u64
+    }
+
+    /// Pass changed settings on to the board workers.
+    fn apply_control(&mut self) {
+        let Some(control) = self.control.as_mut() else {
+            return;
+        };
+        if !control.has_changed().unwrap_or(false) {
+            return;
+        }
+        let current = control.borrow_and_update().clone();
+        self.cfg = current.subscription;
+        self.paused = current.paused;
+        for (name, worker) in &self.workers {
+            worker.control.send_replace(self.board_control(name));
+        }
+    }
+
+    /// Settings of one board's worker.
+    fn board_control(&self, board: &str) -> Control {
+        Control {
+            subscription: Subscription {
+                board_name: board.to_string(),
+                thread_no: None,
+                all_boards_refresh_ms: None,
+                ..self.cfg.clone()
+            },
+            paused: self.paused,
+            board: self.boards.get(board).cloned().map(Arc::new),
+        }
+    }
+
+    /// Fetch the board list, starting and stopping board workers as boards come and go.
+    async fn refresh_boards(&mut self) -> Result<(), Error> {
+        debug!("Refreshing boards");
+        let grants = Grants::new(self.scheduler.clone(), self.cfg.id(), Arc::default());
+        let boards = grants.gate(&self.api).get_boards().await?;
+        let retired = self
+            .boards
+            .keys()
+            .filter(|name| !boards.iter().any(|b| b.name() == name.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in retired {
+            info!("Board retired: {}", name);
+            self.boards.remove(&name);
+            if let Some(worker) = self.workers.remove(&name) {
+                let _ = worker.kill.send(());
+                self.join(name, worker.task).await;
+            }
+        }
+        for board in boards.iter() {
+            let name = board.name();
+            let previous = self.boards.insert(name.to_string(), board.clone());
+            match previous {
+                None => self.start_board(board.clone()),
+                Some(previous) if previous != *board => {
+                    if let Some(worker) = self.workers.get(name) {
+                        worker.control.send_replace(self.board_control(name));
+                    }
+                    if !limits_changed(&previous, board) {
+                        continue;
+                    }
+                    info!("Board metadata changed: {}", name);
+                    let event = Event::BoardMetadataChanged(BoardMetadataChanged {
+                        board: board.name().to_string(),
+                        previous: Arc::new(previous),
+                        current: Arc::new(board.clone()),
+                    });
+                    if let Err(e) = self.events_chan.send(event).await {
+                        error!("Error sending event: {:?}", e);
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        let boards = self.boards.len();
+        self.status.send_modify(|status| {
+            status.state = WorkerState::Running;
+            status.refresh_rate_ms = self.refresh_ms() as i64;
+            status.last_update_sec = self.api.clock().now().timestamp();
+            status.threads = boards;
+        });
+        Ok(())
+    }
+
+    fn start_board(&mut self, board: Board) {
+        let name = board.name().to_string();
+        let (control, control_rx) = tokio::sync::watch::channel(self.board_control(&name));
+        let mut supervisor = Stream::start_worker(
+            self.api.clone(),
+            control.borrow().subscription.clone(),
+            board,
+            self.events_chan.clone(),
+            control_rx,
+            self.checkpoints.clone(),
+            Some(self.scheduler.clone()),
+        );
+        if let Some((min_ms, max_ms)) = self.restart_backoff_ms {
+            supervisor = supervisor.with_backoff(min_ms, max_ms);
+        }
+        let (kill, kill_rx) = tokio::sync::oneshot::channel();
+        let task = tokio::spawn(supervisor.run(kill_rx));
+        self.workers.insert(
+            name,
+            SiteBoard {
+                kill,
+                control,
+                task,
+            },
+        );
+    }
+
+    /// Stop every board worker, keeping their final caches.
+    async fn stop(&mut self) {
+        let workers = self.workers.drain().collect::<Vec<_>>();
+        let mut tasks = vec![];
+        for (name, worker) in workers {
+            let _ = worker.kill.send(());
+            tasks.push((name, worker.task));
+        }
+        for (name, task) in tasks {
+            self.join(name, task).await;
+        }
+    }
+
+    /// Wait for a killed board worker to stop, keeping its final cache.
+    /// A worker that doesn't stop within the shutdown timeout is aborted.
+    async fn join(&mut self, name: String, mut task: tokio::task::JoinHandle<Option<BoardWorker>>) {
+        let shutdown_timeout =
+            tokio::time::Duration::from_millis(self.cfg.shutdown_timeout_ms.max(0) as u64);
+        // In-flight fetches get `shutdown_timeout`, and stopping the worker as long again.
+        match clock::timeout(self.api.clock().as_ref(), shutdown_timeout * 2, &mut task).await {
+            Some(Ok(Some(worker))) => {
+                self.caches.insert(name, worker.into_cache());
+            }
+            Some(Ok(None)) => {}
+            Some(Err(e)) => error!("Error stopping worker {}: {:?}", name, e),
+            None => {
+                error!("Timed out stopping worker {}", name);
+                task.abort();
+            }
+        }
+    }
+}
+
+/// Whether a change to the board's metadata affects its limits or cooldowns,
+/// rather than e.g. its title.
+fn limits_changed(previous: &Board, current: &Board) -> bool {
+    let limits = |board: &Board| {
+        (
+            board.per_page,
+            board.pages,
+            board.max_filesize,
+            board.max_webm_filesize,
+            board.max_comment_chars,
+            board.max_webm_duration,
+            board.bump_limit,
+            board.image_limit,
+        )
+    };
+    limits(previous) != limits(current) || previous.cooldowns != current.cooldowns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rchan_api::{
+        client::Config,
+        endpoint::Endpoint,
+        response::ClientResponse,
+        transport::{ScriptedTransport, Transport, TransportFuture},
+    };
+    use rchan_types::board::Cooldowns;
+
+    /// Serves `ScriptedTransport`'s responses, except for /v/'s catalog, which never loads.
+    #[derive(Debug)]
+    struct HangingTransport(ScriptedTransport);
+
+    impl Transport for HangingTransport {
+        fn get<'a>(&'a self, endpoint: &'a Endpoint, https: bool) -> TransportFuture<'a> {
+            match endpoint {
+                Endpoint::Catalog(board) if board == "v" => Box::pin(futures::future::pending()),
+                _ => self.0.get(endpoint, https),
+            }
+        }
+    }
+
+    fn board(name: &str, replies_cooldown: i32) -> Board {
+        Board {
+            board: name.to_string(),
+            cooldowns: Cooldowns {
+                replies: replies_cooldown,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_refresh_boards() {
+        let transport = Arc::new(ScriptedTransport::new());
+        transport.push(
+            Endpoint::Boards,
+            ClientResponse::Boards(Arc::new(vec![board("g", 60), board("v", 60)])),
+        );
+        transport.push(
+            Endpoint::Boards,
+            ClientResponse::Boards(Arc::new(vec![board("g", 30), board("x", 60)])),
+        );
+        let renamed = Board {
+            title: "Technology".to_string(),
+            ..board("g", 30)
+        };
+        transport.push(
+            Endpoint::Boards,
+            ClientResponse::Boards(Arc::new(vec![renamed, board("x", 60)])),
+        );
+        let client = Arc::new(Client::with_transport(
+            Some(Config::new(None, Some(0))),
+            transport,
+        ));
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let mut worker = SiteWorker::new(client, Subscription::all_boards(None), tx, None)
+            .with_scheduler(Scheduler::new(1));
+
+        worker.refresh_boards().await.unwrap();
+        let mut boards = worker.workers.keys().cloned().collect::<Vec<_>>();
+        boards.sort();
+        assert_eq!(boards, vec!["g", "v"]);
+
+        worker.refresh_boards().await.unwrap();
+        let mut boards = worker.workers.keys().cloned().collect::<Vec<_>>();
+        boards.sort();
+        assert_eq!(boards, vec!["g", "x"]);
+        assert_eq!(worker.status().borrow().threads, 2);
+        // The retired board's worker was stopped and its cache kept.
+        assert!(worker.caches.contains_key("v"));
+        assert_eq!(
+            worker.workers["g"]
+                .control
+                .borrow()
+                .board
+                .as_ref()
+                .map(|b| b.cooldowns.replies),
+            Some(30)
+        );
+
+        // A new title reaches the worker, but isn't a change of limits.
+        worker.refresh_boards().await.unwrap();
+        assert_eq!(
+            worker.workers["g"]
+                .control
+                .borrow()
+                .board
+                .as_ref()
+                .map(|b| b.title.clone()),
+            Some("Technology".to_string())
+        );
+
+        let changed = std::iter::from_fn(|| rx.try_recv().ok())
+            .filter_map(|event| match event {
+                Event::BoardMetadataChanged(changed) => Some(changed),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].board, "g");
+        assert_eq!(changed[0].previous.cooldowns.replies, 60);
+        assert_eq!(changed[0].current.cooldowns.replies, 30);
+        worker.stop().await;
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test(start_paused = true)]
+    async fn test_retire_stuck_board() {
+        let transport = ScriptedTransport::new();
+        transport.push(
+            Endpoint::Boards,
+            ClientResponse::Boards(Arc::new(vec![board("v", 60)])),
+        );
+        transport.push(Endpoint::Boards, ClientResponse::Boards(Arc::new(vec![])));
+        let client = Arc::new(Client::with_transport(
+            Some(Config::new(None, Some(0))),
+            Arc::new(HangingTransport(transport)),
+        ));
+        let (tx, _rx) = tokio::sync::mpsc::channel(100);
+        let mut cfg = Subscription::all_boards(None);
+        cfg.shutdown_timeout_ms = 1000;
+        let mut worker = SiteWorker::new(client, cfg, tx, None).with_scheduler(Scheduler::new(1));
+
+        worker.refresh_boards().await.unwrap();
+        let task = worker.workers["v"].task.abort_handle();
+        // The retired board's worker is stuck on its first fetch, and aborted.
+        tokio::time::timeout(tokio::time::Duration::from_secs(5), worker.refresh_boards())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(worker.workers.is_empty());
+        assert!(logs_contain("Timed out stopping worker v"));
+        tokio::task::yield_now().await;
+        assert!(task.is_finished());
+    }
+}
@@ -0,0 +1,315 @@
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use rchan_api::{
+    clock::{self, Clock},
+    error::Error,
+};
+use tracing::{error, info};
+
+use super::{
+    client::SubscriptionId,
+    site::SiteWorker,
+    thread_worker::ThreadWorker,
+    worker::{
+        AbortOnDrop, BoardWorker, Event, WorkerFailed, WorkerRestarted, WorkerState, WorkerStatus,
+    },
+};
+
+/// A worker that can be run by a `Supervisor`.
+pub trait Supervised: Send + 'static {
+    /// Run until killed, or until the worker fails.
+    /// Running it again after a failure resumes where it left off.
+    fn run(&mut self) -> BoxFuture<'_, Result<(), Error>>;
+    fn set_kill(&mut self, kill: tokio::sync::oneshot::Receiver<()>);
+    fn set_status(&mut self, status: Arc<tokio::sync::watch::Sender<WorkerStatus>>);
+}
+
+impl Supervised for BoardWorker {
+    fn run(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(BoardWorker::run(self))
+    }
+
+    fn set_kill(&mut self, kill: tokio::sync::oneshot::Receiver<()>) {
+        self.kill = Some(kill);
+    }
+
+    fn set_status(&mut self, status: Arc<tokio::sync::watch::Sender<WorkerStatus>>) {
+        self.status = status;
+    }
+}
+
+impl Supervised for ThreadWorker {
+    fn run(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(ThreadWorker::run(self))
+    }
+
+    fn set_kill(&mut self, kill: tokio::sync::oneshot::Receiver<()>) {
+        self.kill = Some(kill);
+    }
+
+    fn set_status(&mut self, status: Arc<tokio::sync::watch::Sender<WorkerStatus>>) {
+        self.status = status;
+    }
+}
+
+impl Supervised for SiteWorker {
+    fn run(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(SiteWorker::run(self))
+    }
+
+    fn set_kill(&mut self, kill: tokio::sync::oneshot::Receiver<()>) {
+        self.kill = Some(kill);
+    }
+
+    fn set_status(&mut self, status: Arc<tokio::sync::watch::Sender<WorkerStatus>>) {
+        self.status = status;
+    }
+}
+
+/// Runs a subscription's worker, restarting it with backoff when it fails or panics.
+/// Failures and restarts are sent as `WorkerFailed` and `WorkerRestarted` events.
+/// A worker that failed is resumed; one that panicked is replaced by a new one.
+/// Workers started by a `Stream` keep a restart checkpoint for their replacement to resume from.
+pub struct Supervisor<W> {
+    id: SubscriptionId,
+    make: Box<dyn FnMut() -> W + Send>,
+    events_chan: tokio::sync::mpsc::Sender<Event>,
+    status: Arc<tokio::sync::watch::Sender<WorkerStatus>>,
+    min_backoff_ms: u64,
+    max_backoff_ms: u64,
+    clock: Arc<dyn Clock>,
+}
+
+impl<W: Supervised> Supervisor<W> {
+    const MIN_BACKOFF_MS: u64 = 1000;
+    const MAX_BACKOFF_MS: u64 = 60000;
+
+    pub fn new(
+        id: SubscriptionId,
+        events_chan: tokio::sync::mpsc::Sender<Event>,
+        make: impl FnMut() -> W + Send + 'static,
+    ) -> Supervisor<W> {
+        let (status, _) = tokio::sync::watch::channel(WorkerStatus::default());
+        Supervisor {
+            id,
+            make: Box::new(make),
+            events_chan,
+            status: Arc::new(status),
+            min_backoff_ms: Self::MIN_BACKOFF_MS,
+            max_backoff_ms: Self::MAX_BACKOFF_MS,
+            clock: clock::system(),
+        }
+    }
+
+    /// Measure runs and wait out backoffs by `clock`'s time.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Supervisor<W> {
+        self.clock = clock;
+        self
+    }
+
+    /// Wait `min_ms` before the first restart, doubling up to `max_ms` while failures continue.
+    pub fn with_backoff(mut self, min_ms: u64, max_ms: u64) -> Supervisor<W> {
+        self.min_backoff_ms = min_ms;
+        self.max_backoff_ms = max_ms.max(min_ms);
+        self
+    }
+
+    pub fn status(&self) -> tokio::sync::watch::Receiver<WorkerStatus> {
+        self.status.subscribe()
+    }
+
+    fn backoff_ms(&self, attempt: u32) -> u64 {
+        self.min_backoff_ms
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(self.max_backoff_ms)
+    }
+
+    /// Supervise until killed, then return the stopped worker.
+    /// Returns None if it was killed while waiting to replace a panicked worker.
+    pub async fn run(mut self, mut kill: tokio::sync::oneshot::Receiver<()>) -> Option<W> {
+        let mut worker = Some((self.make)());
+        let mut attempt = 0;
+        loop {
+            let mut running = worker.take().unwrap_or_else(|| (self.make)());
+            let (kill_tx, kill_rx) = tokio::sync::oneshot::channel();
+            running.set_kill(kill_rx);
+            running.set_status(self.status.clone());
+            let started_ms = self.clock.now_ms();
+            let mut handle = tokio::spawn(async move {
+                let result = running.run().await;
+                (running, result)
+            });
+            // Cancelling the supervisor, e.g. on a shutdown timeout, stops the worker too.
+            let _abort = AbortOnDrop(vec![handle.abort_handle()]);
+            let result = tokio::select! {
+                result = &mut handle => result,
+                _ = &mut kill => {
+                    let _ = kill_tx.send(());
+                    let stopped = handle.await.ok().map(|(stopped, _)| stopped);
+                    self.stopped();
+                    return stopped;
+                }
+            };
+            let error = match result {
+                Ok((stopped, Ok(()))) => {
+                    self.stopped();
+                    return Some(stopped);
+                }
+                Ok((failed, Err(e))) => {
+                    worker = Some(failed);
+                    e.to_string()
+                }
+                Err(e) => format!("Worker panicked: {}", e),
+            };
+            if self.clock.now_ms() - started_ms > self.max_backoff_ms as i64 {
+                attempt = 0;
+            }
+            attempt += 1;
+            error!("Worker {} failed ({}): {}", self.id, attempt, error);
+            self.status.send_modify(|status| {
+                status.state = WorkerState::Failed;
+                status.errors += 1;
+                status.last_error = Some(error.clone());
+            });
+            send(
+                &self.events_chan,
+                Event::WorkerFailed(WorkerFailed {
+                    subscription: self.id.clone(),
+                    error,
+                    attempt,
+                }),
+            )
+            .await;
+
+            let backoff = tokio::time::Duration::from_millis(self.backoff_ms(attempt));
+            tokio::select! {
+                _ = self.clock.sleep(backoff) => {}
+                _ = &mut kill => {
+                    self.stopped();
+                    return worker;
+                }
+            }
+            info!("Restarting worker {} ({})", self.id, attempt);
+            send(
+                &self.events_chan,
+                Event::WorkerRestarted(WorkerRestarted {
+                    subscription: self.id.clone(),
+                    attempt,
+                }),
+            )
+            .await;
+        }
+    }
+
+    fn stopped(&self) {
+        self.status
+            .send_modify(|status| status.state = WorkerState::Stopped);
+    }
+}
+
+async fn send(events_chan: &tokio::sync::mpsc::Sender<Event>, event: Event) {
+    if let Err(e) = events_chan.send(event).await {
+        error!("Error sending event: {:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails the first `failures` runs, then waits to be killed.
+    struct Flaky {
+        failures: u32,
+        panic: bool,
+        kill: Option<tokio::sync::oneshot::Receiver<()>>,
+        status: Arc<tokio::sync::watch::Sender<WorkerStatus>>,
+    }
+
+    impl Supervised for Flaky {
+        fn run(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+            Box::pin(async move {
+                if self.failures > 0 {
+                    self.failures -= 1;
+                    if self.panic {
+                        panic!("flaky worker");
+                    }
+                    return Err(Error::StatusCode("500".to_string()));
+                }
+                self.status
+                    .send_modify(|status| status.state = WorkerState::Running);
+                let _ = self.kill.take().unwrap().await;
+                Ok(())
+            })
+        }
+
+        fn set_kill(&mut self, kill: tokio::sync::oneshot::Receiver<()>) {
+            self.kill = Some(kill);
+        }
+
+        fn set_status(&mut self, status: Arc<tokio::sync::watch::Sender<WorkerStatus>>) {
+            self.status = status;
+        }
+    }
+
+    fn flaky(failures: u32, panic: bool) -> Flaky {
+        Flaky {
+            failures,
+            panic,
+            kill: None,
+            status: Arc::new(tokio::sync::watch::channel(WorkerStatus::default()).0),
+        }
+    }
+
+    async fn supervise(make: impl FnMut() -> Flaky + Send + 'static) -> Vec<Event> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let id = SubscriptionId::Board("g".to_string());
+        let supervisor = Supervisor::new(id, tx, make).with_backoff(1, 10);
+        let mut status = supervisor.status();
+        let (kill_tx, kill_rx) = tokio::sync::oneshot::channel();
+        let handle = tokio::spawn(supervisor.run(kill_rx));
+        status
+            .wait_for(|status| status.state == WorkerState::Running)
+            .await
+            .unwrap();
+        assert_eq!(status.borrow().errors, 2);
+        kill_tx.send(()).unwrap();
+        assert!(handle.await.unwrap().is_some());
+        assert_eq!(status.borrow().state, WorkerState::Stopped);
+        let mut events = vec![];
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_restart_failed() {
+        let events = supervise(|| flaky(2, false)).await;
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            &events[2],
+            Event::WorkerFailed(WorkerFailed { attempt: 2, error, .. }) if error.contains("500")
+        ));
+        assert!(matches!(
+            &events[3],
+            Event::WorkerRestarted(WorkerRestarted { attempt: 2, .. })
+        ));
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_restart_panicked() {
+        let mut made = 0;
+        let events = supervise(move || {
+            made += 1;
+            flaky(if made <= 2 { 1 } else { 0 }, true)
+        })
+        .await;
+        assert!(matches!(
+            &events[0],
+            Event::WorkerFailed(WorkerFailed { error, .. }) if error.contains("panicked")
+        ));
+    }
+}
@@ -0,0 +1,752 @@
+use super::{
+    checkpoint::{Checkpoint, CheckpointStore, MemoryCheckpointStore},
+    client::{Backfill, Control, Subscription, SubscriptionId},
+    filter::Filter,
+    scheduler::{Grants, Scheduler},
+    worker::{
+        control_changed, until_killed, Envelope, Event, ThreadEnd, ThreadEnded, WorkerState,
+        WorkerStatus,
+    },
+};
+use rchan_api::{client::Client, error::Error};
+use rchan_types::{board::Board, post::Thread};
+use std::{collections::HashMap, sync::Arc};
+use tracing::{debug, error, info};
+
+/// Increasing poll intervals for a thread that is not changing,
+/// the same steps 4chan's own thread updater uses.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    min_ms: i64,
+    step: usize,
+}
+
+impl Backoff {
+    const STEPS_MS: [i64; 10] = [
+        10_000, 15_000, 20_000, 30_000, 60_000, 90_000, 120_000, 180_000, 240_000, 300_000,
+    ];
+
+    pub fn new(min_ms: i64) -> Backoff {
+        Backoff { min_ms, step: 0 }
+    }
+
+    /// Time to wait before the next poll.
+    /// Never shorter than the subscription's refresh rate.
+    pub fn interval_ms(&self) -> i64 {
+        Self::STEPS_MS[self.step].max(self.min_ms)
+    }
+
+    /// Nothing changed, wait longer next time.
+    pub fn idle(&mut self) {
+        self.step = (self.step + 1).min(Self::STEPS_MS.len() - 1);
+    }
+
+    /// The thread changed, go back to polling quickly.
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+}
+
+/// Watches a single thread, emitting its new posts until it is archived,
+/// closed or 404s.
+/// Thread watches share the client's rate limit with every other worker.
+pub struct ThreadWorker {
+    api: Arc<Client>,
+    board: String,
+    /// The board's metadata, for event envelopes.
+    board_data: Arc<Board>,
+    no: i32,
+    last_post_no: i32,
+    /// Whether `init` already ran, so a failed worker resumes where it left off.
+    initialized: bool,
+    /// The last post seen, kept for a worker replacing this one.
+    restart: Option<MemoryCheckpointStore>,
+    backoff: Backoff,
+    /// The last fetched thread, when tracking deletions.
+    last_thread: Option<Arc<Thread>>,
+    track_deletions: bool,
+    /// Send the posts already in the thread as historical events on the first fetch.
+    backfill: bool,
+    filter: Option<Filter>,
+    events_chan: tokio::sync::mpsc::Sender<Event>,
+    pub(crate) kill: Option<tokio::sync::oneshot::Receiver<()>>,
+    control: Option<tokio::sync::watch::Receiver<Control>>,
+    paused: bool,
+    /// How long an in-flight fetch may take once killed.
+    grace: tokio::time::Duration,
+    pub(crate) status: Arc<tokio::sync::watch::Sender<WorkerStatus>>,
+    /// Sequence number of the last event sent.
+    seq: u64,
+}
+
+impl ThreadWorker {
+    pub fn new(
+        api: Arc<Client>,
+        cfg: Subscription,
+        no: i32,
+        new_posts_tx: tokio::sync::mpsc::Sender<Event>,
+        kill: Option<tokio::sync::oneshot::Receiver<()>>,
+    ) -> ThreadWorker {
+        let backoff = Backoff::new(cfg.refresh_rate_ms);
+        let (status, _) = tokio::sync::watch::channel(WorkerStatus {
+            refresh_rate_ms: backoff.interval_ms(),
+            threads: 1,
+            ..Default::default()
+        });
+        ThreadWorker {
+            api,
+            board_data: Arc::new(Board {
+                board: cfg.board_name.clone(),
+                ..Default::default()
+            }),
+            board: cfg.board_name,
+            no,
+            last_post_no: 0,
+            initialized: false,
+            restart: None,
+            backoff,
+            last_thread: None,
+            track_deletions: cfg.track_deletions,
+            backfill: cfg.backfill != Backfill::None,
+            filter: cfg.filter,
+            events_chan: new_posts_tx,
+            kill,
+            control: None,
+            paused: false,
+            grace: tokio::time::Duration::from_millis(cfg.shutdown_timeout_ms.max(0) as u64),
+            status: Arc::new(status),
+            seq: 0,
+        }
+    }
+
+    /// Wait for the scheduler's turn before every request, instead of the transport's rate limit.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> ThreadWorker {
+        let id = SubscriptionId::Thread(self.board.clone(), self.no);
+        self.api = Grants::new(scheduler, id, Arc::default()).gate(&self.api);
+        self
+    }
+
+    /// Describe the board with its full metadata in event envelopes, rather than just its name.
+    pub fn with_board(mut self, board: Board) -> ThreadWorker {
+        self.board_data = Arc::new(board);
+        self
+    }
+
+    /// Keep the last post seen in `store` after every fetch and resume from it in `init`,
+    /// so a worker replacing a panicked one neither backfills again nor skips posts.
+    pub fn with_restart_checkpoint(mut self, store: MemoryCheckpointStore) -> ThreadWorker {
+        self.restart = Some(store);
+        self
+    }
+
+    /// Take new settings and pause or resume on changes to `control`.
+    /// Its current value applies right away.
+    pub fn with_control(
+        mut self,
+        mut control: tokio::sync::watch::Receiver<Control>,
+    ) -> ThreadWorker {
+        let current = control.borrow_and_update().clone();
+        self.configure(current.subscription);
+        self.paused = current.paused;
+        if let Some(board) = current.board {
+            self.board_data = board;
+        }
+        self.control = Some(control);
+        self
+    }
+
+    /// Apply new settings. The thread can't change.
+    fn configure(&mut self, cfg: Subscription) {
+        if cfg.refresh_rate_ms != self.backoff.min_ms {
+            self.backoff = Backoff::new(cfg.refresh_rate_ms);
+        }
+        self.track_deletions = cfg.track_deletions;
+        if !self.track_deletions {
+            self.last_thread = None;
+        }
+        self.filter = cfg.filter;
+        self.grace = tokio::time::Duration::from_millis(cfg.shutdown_timeout_ms.max(0) as u64);
+    }
+
+    /// Apply the latest settings, if they changed. Returns whether the worker is paused.
+    fn apply_control(&mut self) -> bool {
+        let Some(control) = self.control.as_mut() else {
+            return self.paused;
+        };
+        if control.has_changed().unwrap_or(false) {
+            let current = control.borrow_and_update().clone();
+            info!(
+                "Applying new settings to thread worker: {}/{}, paused: {}",
+                self.board, self.no, current.paused
+            );
+            self.configure(current.subscription);
+            self.paused = current.paused;
+            if let Some(board) = current.board {
+                self.board_data = board;
+            }
+        }
+        self.paused
+    }
+
+    /// Status updates, sent after every poll.
+    pub fn status(&self) -> tokio::sync::watch::Receiver<WorkerStatus> {
+        self.status.subscribe()
+    }
+
+    pub async fn new_and_run(
+        api: Arc<Client>,
+        cfg: Subscription,
+        no: i32,
+        new_posts_tx: tokio::sync::mpsc::Sender<Event>,
+        kill: Option<tokio::sync::oneshot::Receiver<()>>,
+    ) -> Result<(), Error> {
+        let mut worker = ThreadWorker::new(api, cfg, no, new_posts_tx, kill);
+        worker.run().await
+    }
+
+    /// Mark every post currently in the thread as seen, sending them first when backfilling.
+    /// When resuming from a restart checkpoint, only the posts made since are sent.
+    /// Returns false if the thread has already ended, after emitting `ThreadEnded`.
+    pub async fn init(&mut self) -> Result<bool, Error> {
+        if let Some(checkpoint) = self.load_restart_checkpoint() {
+            self.last_post_no = checkpoint.thread_last_post_no(self.no);
+            self.seq = checkpoint.seq;
+        }
+        match self.fetch().await? {
+            Some(thread) => {
+                let fetched_at_ms = self.api.clock().now_ms();
+                if self.last_post_no > 0 {
+                    self.send_new_posts(&thread, fetched_at_ms).await;
+                } else if self.backfill {
+                    for (ordinal, post) in thread.posts.iter().enumerate() {
+                        let envelope = Envelope::new(
+                            self.board_data.clone(),
+                            post,
+                            thread.op(),
+                            Some(ordinal),
+                            fetched_at_ms,
+                        );
+                        let event = Event::historical(self.board.clone(), Arc::new(post.clone()))
+                            .with_envelope(envelope);
+                        self.send_matching(event, &thread).await;
+                    }
+                }
+                self.last_post_no = thread
+                    .posts
+                    .iter()
+                    .map(|p| p.no)
+                    .fold(self.last_post_no, i32::max);
+                self.save_restart_checkpoint();
+                let ended = self.ended(&thread);
+                if self.track_deletions {
+                    self.last_thread = Some(thread);
+                }
+                match ended {
+                    Some(reason) => {
+                        self.send_ended(reason).await;
+                        Ok(false)
+                    }
+                    None => Ok(true),
+                }
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub async fn run(&mut self) -> Result<(), Error> {
+        if !self.initialized {
+            let mut kill = self.kill.take();
+            let clock = self.api.clock().clone();
+            let (result, killed) =
+                until_killed(&mut kill, clock.as_ref(), self.grace, self.init()).await;
+            self.kill = kill;
+            match result {
+                _ if killed => {
+                    self.stop();
+                    return Ok(());
+                }
+                Some(Ok(true)) => self.initialized = true,
+                Some(Ok(false)) | None => return Ok(()),
+                Some(Err(e)) => return Err(e),
+            }
+        }
+        loop {
+            // New settings cut the sleep short.
+            let clock = self.api.clock().as_ref();
+            let sleep = control_changed(
+                &mut self.control,
+                clock,
+                tokio::time::Duration::from_millis(self.backoff.interval_ms() as u64),
+            );
+            if until_killed(&mut self.kill, clock, tokio::time::Duration::ZERO, sleep)
+                .await
+                .1
+            {
+                self.stop();
+                return Ok(());
+            }
+            if self.apply_control() {
+                self.status
+                    .send_modify(|status| status.state = WorkerState::Paused);
+                while self.apply_control() {
+                    let wait =
+                        tokio::time::Duration::from_millis(self.backoff.interval_ms() as u64);
+                    let clock = self.api.clock().as_ref();
+                    let changed = control_changed(&mut self.control, clock, wait);
+                    if until_killed(&mut self.kill, clock, tokio::time::Duration::ZERO, changed)
+                        .await
+                        .1
+                    {
+                        self.stop();
+                        return Ok(());
+                    }
+                }
+            }
+            let mut kill = self.kill.take();
+            let clock = self.api.clock().clone();
+            let (result, killed) =
+                until_killed(&mut kill, clock.as_ref(), self.grace, self.update_thread()).await;
+            self.kill = kill;
+            match result {
+                _ if killed => {
+                    self.stop();
+                    return Ok(());
+                }
+                Some(Ok(true)) => {}
+                Some(Ok(false)) | None => return Ok(()),
+                Some(Err(e)) => {
+                    error!("Error updating thread {}/{}: {:?}", self.board, self.no, e);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    fn stop(&self) {
+        info!(
+            "Received kill signal, stopping thread worker: {}/{}",
+            self.board, self.no
+        );
+    }
+
+    /// Fetch the thread and emit its new posts.
+    /// Returns false once the thread has ended, after emitting `ThreadEnded`.
+    async fn update_thread(&mut self) -> Result<bool, Error> {
+        debug!("Updating thread: {}/{}", self.board, self.no);
+        let Some(thread) = self.fetch().await? else {
+            return Ok(false);
+        };
+        let fetched_at_ms = self.api.clock().now_ms();
+        let mut changed = self.send_new_posts(&thread, fetched_at_ms).await;
+        self.save_restart_checkpoint();
+        if let Some(prev) = self.last_thread.take() {
+            for event in Event::deletions(&self.board, &prev, &thread) {
+                changed = true;
+                self.send_matching(event, &thread).await;
+            }
+        }
+        if changed {
+            self.backoff.reset();
+        } else {
+            self.backoff.idle();
+        }
+        let refresh_rate_ms = self.backoff.interval_ms();
+        self.status.send_modify(|status| {
+            status.state = WorkerState::Running;
+            status.refresh_rate_ms = refresh_rate_ms;
+            status.last_update_sec = self.api.clock().now().timestamp();
+        });
+        let ended = self.ended(&thread);
+        if self.track_deletions {
+            self.last_thread = Some(thread);
+        }
+        match ended {
+            Some(reason) => {
+                self.send_ended(reason).await;
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+
+    /// Send the posts made since the last one seen. Returns whether there were any.
+    async fn send_new_posts(&mut self, thread: &Thread, fetched_at_ms: i64) -> bool {
+        let last_post_no = self.last_post_no;
+        let mut new_posts = false;
+        for (ordinal, post) in thread.posts.iter().enumerate() {
+            if post.no <= last_post_no {
+                continue;
+            }
+            self.last_post_no = post.no;
+            new_posts = true;
+            let event = Event::fetched(&self.board_data, thread, ordinal, fetched_at_ms);
+            self.send_matching(event, thread).await;
+        }
+        new_posts
+    }
+
+    fn load_restart_checkpoint(&self) -> Option<Checkpoint> {
+        match self.restart.as_ref()?.load(&self.board) {
+            Ok(checkpoint) => checkpoint,
+            Err(e) => {
+                error!(
+                    "Error loading checkpoint for {}/{}: {:?}",
+                    self.board, self.no, e
+                );
+                None
+            }
+        }
+    }
+
+    fn save_restart_checkpoint(&self) {
+        let Some(store) = &self.restart else {
+            return;
+        };
+        let checkpoint = Checkpoint {
+            board: self.board.clone(),
+            last_post_no: self.last_post_no,
+            threads: HashMap::from([(self.no, self.last_post_no)]),
+            seq: self.seq,
+        };
+        if let Err(e) = store.save(&checkpoint) {
+            error!(
+                "Error saving checkpoint for {}/{}: {:?}",
+                self.board, self.no, e
+            );
+        }
+    }
+
+    /// Fetch the thread, emitting `ThreadEnded` if it 404s.
+    async fn fetch(&mut self) -> Result<Option<Arc<Thread>>, Error> {
+        match self.api.get_thread(&self.board, self.no).await {
+            Ok(thread) => Ok(Some(thread)),
+            Err(Error::StatusCode(code)) if code == "404" => {
+                self.send_ended(ThreadEnd::NotFound).await;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn ended(&self, thread: &Thread) -> Option<ThreadEnd> {
+        if thread.is_archived() {
+            Some(ThreadEnd::Archived {
+                archived_on: thread.archived_on(),
+            })
+        } else if thread.op().is_some_and(|op| op.is_closed()) {
+            Some(ThreadEnd::Closed)
+        } else {
+            None
+        }
+    }
+
+    async fn send_ended(&mut self, reason: ThreadEnd) {
+        info!("Thread {}/{} ended: {:?}", self.board, self.no, reason);
+        self.send(Event::ThreadEnded(ThreadEnded {
+            board: self.board.clone(),
+            no: self.no,
+            reason,
+            seq: 0,
+        }))
+        .await;
+    }
+
+    /// Send the event if it matches the subscription's filter.
+    async fn send_matching(&mut self, event: Event, thread: &Thread) {
+        if self
+            .filter
+            .as_ref()
+            .is_none_or(|f| f.matches_event(&event, thread.op()))
+        {
+            self.send(event).await;
+        }
+    }
+
+    async fn send(&mut self, mut event: Event) {
+        self.seq += 1;
+        event.stamp(self.seq, self.api.clock().now_ms());
+        if let Err(e) = self.events_chan.send(event).await {
+            error!("Error sending event: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::{NewPost, NewThread};
+    use rchan_api::{
+        client::Config,
+        endpoint::Endpoint,
+        response::ClientResponse,
+        transport::{ScriptedTransport, Transport, TransportFuture},
+    };
+    use rchan_types::post::Post;
+
+    /// A transport whose requests never finish.
+    #[derive(Debug)]
+    struct HangingTransport;
+
+    impl Transport for HangingTransport {
+        fn get<'a>(&'a self, _endpoint: &'a Endpoint, _https: bool) -> TransportFuture<'a> {
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    fn thread(nos: &[i32]) -> ClientResponse {
+        ClientResponse::Thread(Arc::new(Thread {
+            posts: nos
+                .iter()
+                .map(|no| Post {
+                    no: *no,
+                    resto: Some(if *no == nos[0] { 0 } else { nos[0] }),
+                    ..Default::default()
+                })
+                .collect(),
+        }))
+    }
+
+    fn scripted_worker(
+        transport: Arc<ScriptedTransport>,
+    ) -> (ThreadWorker, tokio::sync::mpsc::Receiver<Event>) {
+        let client = Arc::new(Client::with_transport(
+            Some(Config::new(None, Some(0))),
+            transport,
+        ));
+        let (tx, rx) = tokio::sync::mpsc::channel(100);
+        let cfg = Subscription::thread("g".to_string(), 1, None);
+        (ThreadWorker::new(client, cfg, 1, tx, None), rx)
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(12_000);
+        assert_eq!(backoff.interval_ms(), 12_000);
+        backoff.idle();
+        assert_eq!(backoff.interval_ms(), 15_000);
+        for _ in 0..20 {
+            backoff.idle();
+        }
+        assert_eq!(backoff.interval_ms(), 300_000);
+        backoff.reset();
+        assert_eq!(backoff.interval_ms(), 12_000);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_watch_until_archived() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let endpoint = Endpoint::Thread("g".to_string(), 1);
+        transport.push(endpoint.clone(), thread(&[1, 2]));
+        transport.push(endpoint.clone(), thread(&[1, 2]));
+        transport.push(endpoint.clone(), thread(&[1, 2, 3, 4]));
+        transport.push(endpoint.clone(), archived(&[1, 2, 3, 4, 5]));
+
+        let (mut worker, mut rx) = scripted_worker(transport);
+        assert!(worker.init().await.unwrap());
+        assert!(worker.update_thread().await.unwrap());
+        assert!(rx.try_recv().is_err());
+        assert_eq!(worker.backoff.interval_ms(), 15_000);
+
+        assert!(worker.update_thread().await.unwrap());
+        assert_eq!(worker.backoff.interval_ms(), 10_000);
+        for expected in [3, 4] {
+            assert!(matches!(rx.try_recv(), Ok(Event::NewPost(p)) if p.post.no == expected));
+        }
+
+        assert!(!worker.update_thread().await.unwrap());
+        assert!(matches!(rx.try_recv(), Ok(Event::NewPost(p)) if p.post.no == 5));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::ThreadEnded(ThreadEnded {
+                reason: ThreadEnd::Archived {
+                    archived_on: Some(1000)
+                },
+                seq: 4,
+                ..
+            }))
+        ));
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_watch_deletions() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let endpoint = Endpoint::Thread("g".to_string(), 1);
+        let ClientResponse::Thread(before) = thread(&[1, 2, 3]) else {
+            unreachable!()
+        };
+        let mut before = (*before).clone();
+        before.posts[2].tim = Some(1);
+        before.posts[2].filename = Some("file".to_string());
+        let mut after = before.clone();
+        after.posts.remove(1);
+        after.posts[1].filedeleted = Some(1);
+        transport.push(endpoint.clone(), ClientResponse::Thread(Arc::new(before)));
+        transport.push(endpoint.clone(), ClientResponse::Thread(Arc::new(after)));
+
+        let (mut worker, mut rx) = scripted_worker(transport);
+        worker.track_deletions = true;
+        assert!(worker.init().await.unwrap());
+        assert!(worker.update_thread().await.unwrap());
+        assert!(matches!(rx.try_recv(), Ok(Event::PostDeleted(d)) if d.post.no == 2));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::FileDeleted(d)) if d.post.no == 3 && d.post.filename.as_deref() == Some("file")
+        ));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_watch_filter() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let endpoint = Endpoint::Thread("g".to_string(), 1);
+        transport.push(endpoint.clone(), thread(&[1]));
+        let ClientResponse::Thread(next) = thread(&[1, 2, 3]) else {
+            unreachable!()
+        };
+        let mut next = (*next).clone();
+        next.posts[2].tim = Some(1);
+        transport.push(endpoint.clone(), ClientResponse::Thread(Arc::new(next)));
+
+        let (mut worker, mut rx) = scripted_worker(transport);
+        worker.filter = Some("file".parse().unwrap());
+        assert!(worker.init().await.unwrap());
+        assert!(worker.update_thread().await.unwrap());
+        assert!(matches!(rx.try_recv(), Ok(Event::NewPost(p)) if p.post.no == 3));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(worker.last_post_no, 3);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_watch_not_found() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let endpoint = Endpoint::Thread("g".to_string(), 1);
+        transport.push(endpoint.clone(), thread(&[1]));
+        transport.push_status(endpoint.clone(), 404);
+
+        let (mut worker, mut rx) = scripted_worker(transport);
+        assert!(worker.init().await.unwrap());
+        assert!(!worker.update_thread().await.unwrap());
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::ThreadEnded(ThreadEnded {
+                reason: ThreadEnd::NotFound,
+                ..
+            }))
+        ));
+    }
+
+    fn archived(nos: &[i32]) -> ClientResponse {
+        let ClientResponse::Thread(thread) = thread(nos) else {
+            unreachable!()
+        };
+        let mut thread = (*thread).clone();
+        thread.posts[0].archived = Some(1);
+        thread.posts[0].archived_on = Some(1000);
+        ClientResponse::Thread(Arc::new(thread))
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_watch_already_archived() {
+        let transport = Arc::new(ScriptedTransport::new());
+        transport.push(Endpoint::Thread("g".to_string(), 1), archived(&[1, 2]));
+
+        let (mut worker, mut rx) = scripted_worker(transport);
+        worker.run().await.unwrap();
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::ThreadEnded(ThreadEnded {
+                reason: ThreadEnd::Archived {
+                    archived_on: Some(1000)
+                },
+                seq: 1,
+                ..
+            }))
+        ));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test(start_paused = true)]
+    async fn test_resume_after_error() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let endpoint = Endpoint::Thread("g".to_string(), 1);
+        transport.push(endpoint.clone(), thread(&[1]));
+        transport.push_status(endpoint.clone(), 500);
+        transport.push(endpoint.clone(), archived(&[1, 2]));
+
+        let (mut worker, mut rx) = scripted_worker(transport.clone());
+        assert!(worker.run().await.is_err());
+        assert!(rx.try_recv().is_err());
+
+        // Resumes without marking the posts made since as seen.
+        worker.run().await.unwrap();
+        assert_eq!(transport.requests().len(), 3);
+        assert!(matches!(rx.try_recv(), Ok(Event::NewPost(p)) if p.post.no == 2));
+        assert!(matches!(rx.try_recv(), Ok(Event::ThreadEnded(_))));
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_restart_checkpoint() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let endpoint = Endpoint::Thread("g".to_string(), 1);
+        transport.push(endpoint.clone(), thread(&[1, 2]));
+        transport.push(endpoint.clone(), thread(&[1, 2, 3]));
+        let store = MemoryCheckpointStore::new();
+
+        let (worker, mut rx) = scripted_worker(transport.clone());
+        let mut worker = worker.with_restart_checkpoint(store.clone());
+        worker.backfill = true;
+        assert!(worker.init().await.unwrap());
+        for expected in [1, 2] {
+            assert!(matches!(
+                rx.try_recv(),
+                Ok(Event::NewThread(NewThread { post, historical: true, .. })
+                    | Event::NewPost(NewPost { post, historical: true, .. }))
+                    if post.no == expected
+            ));
+        }
+
+        // A replacement for a panicked worker picks up from the last post seen.
+        let (worker, mut rx) = scripted_worker(transport);
+        let mut worker = worker.with_restart_checkpoint(store);
+        worker.backfill = true;
+        assert!(worker.init().await.unwrap());
+        // Its events are numbered on from the last one sent.
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::NewPost(NewPost { post, historical: false, seq: 3, .. })) if post.no == 3
+        ));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test(start_paused = true)]
+    async fn test_stop_during_init() {
+        let client = Arc::new(Client::with_transport(
+            Some(Config::new(None, Some(0))),
+            Arc::new(HangingTransport),
+        ));
+        let (tx, _rx) = tokio::sync::mpsc::channel(100);
+        let (kill, kill_rx) = tokio::sync::oneshot::channel();
+        let mut cfg = Subscription::thread("g".to_string(), 1, None);
+        cfg.shutdown_timeout_ms = 1000;
+        let mut worker = ThreadWorker::new(client, cfg, 1, tx, Some(kill_rx));
+        let run = tokio::spawn(async move { worker.run().await });
+        tokio::task::yield_now().await;
+
+        // The first fetch never finishes, and is dropped once the grace period is over.
+        kill.send(()).unwrap();
+        tokio::time::timeout(tokio::time::Duration::from_secs(5), run)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(logs_contain("Received kill signal"));
+    }
+}
@@ -1,38 +1,412 @@
-use super::client::Subscription;
-use rchan_api::{client::Client, error::Error};
+use super::{
+    checkpoint::{Checkpoint, CheckpointStore, MemoryCheckpointStore},
+    client::{Backfill, Control, EventOrder, RefreshRange, Subscription, SubscriptionId},
+    scheduler::{Grants, Scheduler},
+};
+use futures::StreamExt;
+use rchan_api::{
+    client::Client,
+    clock::{self, Clock},
+    error::Error,
+};
 use rchan_types::{
+    archive::ThreadStatus,
     board::Board,
-    post::{Post, ThreadPage},
+    post::{Post, Thread},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
 };
-use std::{collections::HashMap, sync::Arc};
 use tracing::{debug, error, info};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewPost {
     pub board: String,
     pub post: Arc<Post>,
+    /// Where and when the post was found, set on events sent by a worker.
+    #[serde(default)]
+    pub envelope: Option<Envelope>,
+    /// Made before the subscription started, sent by a backfill.
+    #[serde(default)]
+    pub historical: bool,
+    /// Sequence number among the board's events, see `Event::seq`.
+    #[serde(default)]
+    pub seq: u64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewThread {
     pub board: String,
     pub post: Arc<Post>,
+    /// Where and when the post was found, set on events sent by a worker.
+    #[serde(default)]
+    pub envelope: Option<Envelope>,
+    /// Made before the subscription started, sent by a backfill.
+    #[serde(default)]
+    pub historical: bool,
+    /// Sequence number among the board's events, see `Event::seq`.
+    #[serde(default)]
+    pub seq: u64,
+}
+
+/// Context of a new post, filled in by the worker that fetched it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    /// The same for the post's event in every process and across restarts,
+    /// so consumers can deduplicate.
+    pub id: String,
+    pub board: Arc<Board>,
+    /// The thread's OP number, the post's own number for an OP.
+    pub op_no: i32,
+    /// The thread's subject, without markup.
+    pub subject: Option<String>,
+    /// Position of the post in its thread as fetched, 0 for the OP.
+    /// None if only part of the thread was seen.
+    pub ordinal: Option<usize>,
+    /// When the post was fetched, in milliseconds since the epoch.
+    pub fetched_at_ms: i64,
+    /// Time from the post being made to its event being sent.
+    pub latency_ms: Option<i64>,
+}
+
+impl Envelope {
+    /// `op` is the OP of the post's thread, for the subject.
+    pub fn new(
+        board: Arc<Board>,
+        post: &Post,
+        op: Option<&Post>,
+        ordinal: Option<usize>,
+        fetched_at_ms: i64,
+    ) -> Envelope {
+        Envelope {
+            id: format!("{}/{}", board.name(), post.no),
+            board,
+            op_no: post.thread_no(),
+            subject: op.and_then(|op| op.clean_title()),
+            ordinal,
+            fetched_at_ms,
+            latency_ms: None,
+        }
+    }
+}
+
+/// Why a watched thread stopped being watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThreadEnd {
+    Archived { archived_on: Option<i64> },
+    Closed,
+    NotFound,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadEnded {
+    pub board: String,
+    pub no: i32,
+    pub reason: ThreadEnd,
+    /// Sequence number among the board's events, see `Event::seq`.
+    #[serde(default)]
+    pub seq: u64,
+}
+
+/// A change in a thread's state on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThreadChange {
+    /// The thread dropped off the board, either into the archive
+    /// (`ThreadStatus::Archived`) or pruned/deleted (`ThreadStatus::Removed`).
+    Removed(ThreadStatus),
+    Closed,
+    Reopened,
+    Stickied,
+    Unstickied,
+    BumpLimitReached,
+    ImageLimitReached,
+    /// Only sent with `Subscription::track_pages`.
+    PageChanged {
+        from: i32,
+        to: i32,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadChanged {
+    pub board: String,
+    pub no: i32,
+    pub change: ThreadChange,
+    /// Sequence number among the board's events, see `Event::seq`.
+    #[serde(default)]
+    pub seq: u64,
+}
+
+/// A post that was deleted, with its last known content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostDeleted {
+    pub board: String,
+    pub thread_no: i32,
+    pub post: Arc<Post>,
+    /// Sequence number among the board's events, see `Event::seq`.
+    #[serde(default)]
+    pub seq: u64,
+}
+
+/// A post whose file was deleted, with the post as it was before.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileDeleted {
+    pub board: String,
+    pub thread_no: i32,
+    pub post: Arc<Post>,
+    /// Sequence number among the board's events, see `Event::seq`.
+    #[serde(default)]
+    pub seq: u64,
+}
+
+/// A subscription's worker stopped with an error, and will be restarted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerFailed {
+    pub subscription: SubscriptionId,
+    pub error: String,
+    /// Failures in a row, including this one.
+    pub attempt: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerRestarted {
+    pub subscription: SubscriptionId,
+    pub attempt: u32,
+}
+
+/// A board's settings changed, e.g. its limits or cooldowns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoardMetadataChanged {
+    pub board: String,
+    pub previous: Arc<Board>,
+    pub current: Arc<Board>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Event {
     NewPost(NewPost),
     NewThread(NewThread),
+    ThreadEnded(ThreadEnded),
+    ThreadChanged(ThreadChanged),
+    PostDeleted(PostDeleted),
+    FileDeleted(FileDeleted),
+    WorkerFailed(WorkerFailed),
+    WorkerRestarted(WorkerRestarted),
+    BoardMetadataChanged(BoardMetadataChanged),
 }
 
 impl Event {
     pub fn new(board: String, post: Arc<Post>) -> Event {
+        Self::new_post(board, post, false)
+    }
+
+    /// A post made before the subscription started.
+    pub fn historical(board: String, post: Arc<Post>) -> Event {
+        Self::new_post(board, post, true)
+    }
+
+    fn new_post(board: String, post: Arc<Post>, historical: bool) -> Event {
         if post.is_op() {
-            Event::NewThread(NewThread { board, post })
+            Event::NewThread(NewThread {
+                board,
+                post,
+                historical,
+                envelope: None,
+                seq: 0,
+            })
         } else {
-            Event::NewPost(NewPost { board, post })
+            Event::NewPost(NewPost {
+                board,
+                post,
+                historical,
+                envelope: None,
+                seq: 0,
+            })
+        }
+    }
+
+    /// The `ordinal`th post of a thread fetched at `fetched_at_ms`.
+    pub fn fetched(
+        board: &Arc<Board>,
+        thread: &Thread,
+        ordinal: usize,
+        fetched_at_ms: i64,
+    ) -> Event {
+        let post = &thread.posts[ordinal];
+        Event::new(board.name().to_string(), Arc::new(post.clone())).with_envelope(Envelope::new(
+            board.clone(),
+            post,
+            thread.op(),
+            Some(ordinal),
+            fetched_at_ms,
+        ))
+    }
+
+    /// Attach context to a `NewPost` or `NewThread`. Other events have none.
+    pub fn with_envelope(mut self, envelope: Envelope) -> Event {
+        match &mut self {
+            Event::NewPost(e) => e.envelope = Some(envelope),
+            Event::NewThread(e) => e.envelope = Some(envelope),
+            _ => {}
+        }
+        self
+    }
+
+    pub fn envelope(&self) -> Option<&Envelope> {
+        match self {
+            Event::NewPost(e) => e.envelope.as_ref(),
+            Event::NewThread(e) => e.envelope.as_ref(),
+            _ => None,
         }
     }
+
+    pub fn thread_changed(board: String, no: i32, change: ThreadChange) -> Event {
+        Event::ThreadChanged(ThreadChanged {
+            board,
+            no,
+            change,
+            seq: 0,
+        })
+    }
+
+    /// The event's sequence number among the events of its board, or thread for a
+    /// thread subscription. Workers number events from 1 as they send them, so a
+    /// skipped number means events were dropped on the way, e.g. by
+    /// `Backpressure::DropOldest`. A panicked worker's replacement numbers on from the end
+    /// of the last cycle it finished, so events it sends again get their numbers again.
+    /// Always 0 for worker and board metadata events, which aren't numbered.
+    pub fn seq(&self) -> u64 {
+        match self {
+            Event::NewPost(e) => e.seq,
+            Event::NewThread(e) => e.seq,
+            Event::ThreadEnded(e) => e.seq,
+            Event::ThreadChanged(e) => e.seq,
+            Event::PostDeleted(e) => e.seq,
+            Event::FileDeleted(e) => e.seq,
+            Event::WorkerFailed(_) | Event::WorkerRestarted(_) | Event::BoardMetadataChanged(_) => {
+                0
+            }
+        }
+    }
+
+    /// Number the event as it is sent at `sent_at_ms`, recording its latency.
+    pub(crate) fn stamp(&mut self, seq: u64, sent_at_ms: i64) {
+        let latency_ms = self
+            .post()
+            .and_then(|post| post.time)
+            .map(|time| sent_at_ms - time * 1000);
+        let envelope = match self {
+            Event::NewPost(e) => e.envelope.as_mut(),
+            Event::NewThread(e) => e.envelope.as_mut(),
+            _ => None,
+        };
+        if let Some(envelope) = envelope {
+            envelope.latency_ms = latency_ms;
+        }
+        match self {
+            Event::NewPost(e) => e.seq = seq,
+            Event::NewThread(e) => e.seq = seq,
+            Event::ThreadEnded(e) => e.seq = seq,
+            Event::ThreadChanged(e) => e.seq = seq,
+            Event::PostDeleted(e) => e.seq = seq,
+            Event::FileDeleted(e) => e.seq = seq,
+            Event::WorkerFailed(_) | Event::WorkerRestarted(_) | Event::BoardMetadataChanged(_) => {
+            }
+        }
+    }
+
+    /// The post the event is about, if any.
+    pub fn post(&self) -> Option<&Arc<Post>> {
+        match self {
+            Event::NewPost(e) => Some(&e.post),
+            Event::NewThread(e) => Some(&e.post),
+            Event::PostDeleted(e) => Some(&e.post),
+            Event::FileDeleted(e) => Some(&e.post),
+            _ => None,
+        }
+    }
+
+    /// `PostDeleted` and `FileDeleted` events for everything removed between
+    /// two fetches of the same thread.
+    pub fn deletions(board: &str, prev: &Thread, next: &Thread) -> Vec<Event> {
+        let next_posts = next
+            .posts
+            .iter()
+            .map(|post| (post.no, post))
+            .collect::<HashMap<_, _>>();
+        let mut events = vec![];
+        for post in &prev.posts {
+            match next_posts.get(&post.no) {
+                None => events.push(Event::PostDeleted(PostDeleted {
+                    board: board.to_string(),
+                    thread_no: post.thread_no(),
+                    post: Arc::new(post.clone()),
+                    seq: 0,
+                })),
+                Some(next_post)
+                    if post.has_image()
+                        && !post.is_file_deleted()
+                        && next_post.is_file_deleted() =>
+                {
+                    events.push(Event::FileDeleted(FileDeleted {
+                        board: board.to_string(),
+                        thread_no: post.thread_no(),
+                        post: Arc::new(post.clone()),
+                        seq: 0,
+                    }))
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+/// Thread state carried on the OP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreadFlags {
+    pub sticky: bool,
+    pub closed: bool,
+    pub bump_limit: bool,
+    pub image_limit: bool,
+}
+
+impl ThreadFlags {
+    pub fn new(op: &Post) -> ThreadFlags {
+        ThreadFlags {
+            sticky: op.is_sticky(),
+            closed: op.is_closed(),
+            bump_limit: op.bump_limit_reached(),
+            image_limit: op.image_limit_reached(),
+        }
+    }
+
+    /// The changes going from these flags to `next`.
+    pub fn changes(&self, next: &ThreadFlags) -> Vec<ThreadChange> {
+        let mut changes = vec![];
+        match (self.closed, next.closed) {
+            (false, true) => changes.push(ThreadChange::Closed),
+            (true, false) => changes.push(ThreadChange::Reopened),
+            _ => {}
+        }
+        match (self.sticky, next.sticky) {
+            (false, true) => changes.push(ThreadChange::Stickied),
+            (true, false) => changes.push(ThreadChange::Unstickied),
+            _ => {}
+        }
+        if !self.bump_limit && next.bump_limit {
+            changes.push(ThreadChange::BumpLimitReached);
+        }
+        if !self.image_limit && next.image_limit {
+            changes.push(ThreadChange::ImageLimitReached);
+        }
+        changes
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -40,6 +414,14 @@ pub struct ThreadCache {
     no: i32,
     last_modified: i64,
     prev_last_modified: i64,
+    /// Highest post number emitted for this thread.
+    /// Post numbers only go up, so anything above it is new.
+    last_post_no: i32,
+    /// Flags from the last time the OP was seen, None until then.
+    flags: Option<ThreadFlags>,
+    page: Option<i32>,
+    /// The last fetched thread, when tracking deletions.
+    last_thread: Option<Arc<Thread>>,
 }
 
 impl ThreadCache {
@@ -48,6 +430,10 @@ impl ThreadCache {
             no,
             last_modified,
             prev_last_modified: 0,
+            last_post_no: 0,
+            flags: None,
+            page: None,
+            last_thread: None,
         }
     }
 }
@@ -55,25 +441,260 @@ impl ThreadCache {
 #[derive(Debug, Clone)]
 pub struct BoardCache {
     last_update_sec: i64,
+    /// Highest post number on the board when the worker started.
+    /// Posts up to it were made before we were watching.
+    init_post_no: i32,
     threads: HashMap<i32, ThreadCache>,
+    /// Threads that dropped off threads.json, kept so a thread that
+    /// shows up again does not have its posts emitted twice.
+    removed: HashMap<i32, ThreadCache>,
+    thread_limit: usize,
 }
 
 impl BoardCache {
     pub fn new(thread_limit: usize) -> BoardCache {
         BoardCache {
             last_update_sec: 0,
+            init_post_no: 0,
             threads: HashMap::with_capacity(thread_limit),
+            removed: HashMap::new(),
+            thread_limit,
+        }
+    }
+
+    /// Time of the last update, 0 before the first one.
+    pub fn last_update_sec(&self) -> i64 {
+        self.last_update_sec
+    }
+
+    /// Highest post numbers emitted, to resume from.
+    pub fn checkpoint(&self, board: &str) -> Checkpoint {
+        let threads = self
+            .threads
+            .values()
+            .map(|thread| (thread.no, thread.last_post_no))
+            .collect::<HashMap<_, _>>();
+        Checkpoint {
+            board: board.to_string(),
+            last_post_no: threads.values().copied().fold(self.init_post_no, i32::max),
+            threads,
+            seq: 0,
+        }
+    }
+
+    fn remove(&mut self, no: i32) {
+        if let Some(thread) = self.threads.remove(&no) {
+            self.removed.insert(no, thread);
+        }
+        while self.removed.len() > self.thread_limit {
+            if let Some(oldest) = self.removed.keys().min().copied() {
+                self.removed.remove(&oldest);
+            }
+        }
+    }
+
+    fn get_or_insert(&mut self, no: i32) -> &mut ThreadCache {
+        let init_post_no = self.init_post_no;
+        let removed = self.removed.remove(&no);
+        self.threads.entry(no).or_insert_with(|| {
+            removed.unwrap_or_else(|| ThreadCache {
+                last_post_no: init_post_no,
+                ..ThreadCache::new(no, 0)
+            })
+        })
+    }
+}
+
+/// Aborts tasks when it is dropped, e.g. the fetches of a cancelled update cycle.
+pub(crate) struct AbortOnDrop(pub(crate) Vec<tokio::task::AbortHandle>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.iter().for_each(|task| task.abort());
+    }
+}
+
+/// Run `fut` unless the worker is killed first, or its kill channel closed.
+/// Once killed, `fut` has `grace` to finish before it is dropped.
+/// Returns its output, if it finished, and whether the worker was killed.
+pub(crate) async fn until_killed<F: std::future::Future>(
+    kill: &mut Option<tokio::sync::oneshot::Receiver<()>>,
+    clock: &dyn Clock,
+    grace: tokio::time::Duration,
+    fut: F,
+) -> (Option<F::Output>, bool) {
+    let Some(rx) = kill else {
+        return (Some(fut.await), false);
+    };
+    tokio::pin!(fut);
+    tokio::select! {
+        output = &mut fut => (Some(output), false),
+        _ = rx => (clock::timeout(clock, grace, fut).await, true),
+    }
+}
+
+/// Wait for new settings on `control`, for at most `timeout`.
+pub(crate) async fn control_changed(
+    control: &mut Option<tokio::sync::watch::Receiver<Control>>,
+    clock: &dyn Clock,
+    timeout: tokio::time::Duration,
+) {
+    let closed = match control {
+        Some(rx) => match clock::timeout(clock, timeout, rx.changed()).await {
+            Some(Ok(())) => {
+                // Leave the new settings for the worker to apply.
+                rx.mark_changed();
+                false
+            }
+            Some(Err(_)) => true,
+            None => false,
+        },
+        None => {
+            clock.sleep(timeout).await;
+            false
+        }
+    };
+    if closed {
+        *control = None;
+    }
+}
+
+/// Outcome of fetching a modified thread.
+enum ThreadFetch {
+    Fetched {
+        no: i32,
+        /// Events to send, already filtered.
+        events: Vec<Event>,
+        last_post_no: i32,
+        flags: Option<ThreadFlags>,
+        thread: Option<Arc<Thread>>,
+    },
+    Failed {
+        no: i32,
+    },
+}
+
+/// Difference between the cache and a new threads.json snapshot.
+#[derive(Debug, Default)]
+struct CacheDiff {
+    /// New and modified threads, by last_modified.
+    modified: Vec<Post>,
+    /// Threads no longer on the board.
+    removed: Vec<i32>,
+    /// Threads that moved to another page, (no, from, to).
+    moved: Vec<(i32, i32, i32)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WorkerState {
+    #[default]
+    Starting,
+    Running,
+    Paused,
+    /// Failed, waiting to be restarted.
+    Failed,
+    Stopped,
+}
+
+/// What a worker is doing, as last reported by it and its supervisor.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkerStatus {
+    pub state: WorkerState,
+    /// Current time between polls.
+    pub refresh_rate_ms: i64,
+    /// Time of the last successful update, 0 before the first one.
+    pub last_update_sec: i64,
+    /// Threads currently on the board, 1 for a thread watch, or boards watched for all boards.
+    pub threads: usize,
+    /// Failures since the subscription started.
+    pub errors: u64,
+    /// Events waiting to be delivered to the subscription's consumer.
+    pub queue_depth: usize,
+    pub last_error: Option<String>,
+}
+
+/// Time between `threads.json` polls of a board.
+/// Fixed at the subscription's refresh rate, unless it has a `RefreshRange`:
+/// then the interval grows while the board is quiet and shrinks while
+/// threads keep being modified. An unchanged snapshot, such as a 304,
+/// counts as a quiet poll.
+#[derive(Debug, Clone)]
+pub struct PollInterval {
+    interval_ms: i64,
+    range: Option<RefreshRange>,
+    /// Modified threads per poll, most recent last.
+    window: VecDeque<usize>,
+}
+
+impl PollInterval {
+    const WINDOW: usize = 10;
+
+    pub fn new(refresh_rate_ms: i64, range: Option<RefreshRange>) -> PollInterval {
+        let interval_ms = match &range {
+            Some(range) => refresh_rate_ms.clamp(range.min_ms, range.max_ms),
+            None => refresh_rate_ms,
+        };
+        PollInterval {
+            interval_ms,
+            range,
+            window: VecDeque::with_capacity(Self::WINDOW),
+        }
+    }
+
+    pub fn interval_ms(&self) -> i64 {
+        self.interval_ms
+    }
+
+    /// Threads modified in the last poll.
+    pub fn activity(&self) -> u32 {
+        self.window.back().copied().unwrap_or(0) as u32
+    }
+
+    /// Record the number of threads modified in a poll and adjust the interval.
+    pub fn record(&mut self, modified: usize) {
+        let Some(range) = &self.range else {
+            return;
+        };
+        if self.window.len() == Self::WINDOW {
+            self.window.pop_front();
         }
+        self.window.push_back(modified);
+        let polls = self.window.len() as f64;
+        let unchanged_ratio = self.window.iter().filter(|m| **m == 0).count() as f64 / polls;
+        let modified_rate = self.window.iter().sum::<usize>() as f64 / polls;
+        let interval_ms = if modified == 0 {
+            self.interval_ms * 3 / 2
+        } else if modified_rate >= 1.0 && unchanged_ratio < 0.5 {
+            self.interval_ms / 2
+        } else {
+            self.interval_ms
+        };
+        self.interval_ms = interval_ms.clamp(range.min_ms, range.max_ms);
     }
 }
 
 pub struct BoardWorker {
     api: Arc<Client>,
     cfg: Subscription,
-    board: Board,
+    board: Arc<Board>,
     cache: BoardCache,
     events_chan: tokio::sync::mpsc::Sender<Event>,
-    kill: Option<tokio::sync::oneshot::Receiver<()>>,
+    pub(crate) kill: Option<tokio::sync::oneshot::Receiver<()>>,
+    checkpoints: Option<Arc<dyn CheckpointStore>>,
+    last_checkpoint_ms: i64,
+    /// Progress kept after every update for a worker replacing this one.
+    restart: Option<MemoryCheckpointStore>,
+    interval: PollInterval,
+    initialized: bool,
+    control: Option<tokio::sync::watch::Receiver<Control>>,
+    paused: bool,
+    pub(crate) status: Arc<tokio::sync::watch::Sender<WorkerStatus>>,
+    /// Threads modified in the last poll, for the scheduler to prioritize by.
+    activity: Arc<AtomicU32>,
+    /// Sequence number of the last event sent.
+    seq: u64,
+    /// Events of the current cycle, waiting to be sorted, unless sent as they arrive.
+    pending: Vec<Event>,
 }
 
 impl BoardWorker {
@@ -85,14 +706,106 @@ impl BoardWorker {
         kill: Option<tokio::sync::oneshot::Receiver<()>>,
     ) -> BoardWorker {
         let cache = BoardCache::new(board.thread_limit() as usize);
+        let interval = PollInterval::new(cfg.refresh_rate_ms, cfg.adaptive_refresh);
+        let (status, _) = tokio::sync::watch::channel(WorkerStatus {
+            refresh_rate_ms: interval.interval_ms(),
+            ..Default::default()
+        });
         BoardWorker {
             api,
             cfg,
-            board,
+            board: Arc::new(board),
             cache,
             events_chan: new_posts_tx,
             kill,
+            checkpoints: None,
+            last_checkpoint_ms: 0,
+            restart: None,
+            interval,
+            initialized: false,
+            control: None,
+            paused: false,
+            status: Arc::new(status),
+            activity: Arc::default(),
+            seq: 0,
+            pending: vec![],
+        }
+    }
+
+    /// Wait for the scheduler's turn before every request, instead of the transport's rate limit.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> BoardWorker {
+        self.api = Grants::new(scheduler, self.cfg.id(), self.activity.clone()).gate(&self.api);
+        self
+    }
+
+    /// Take new settings and pause or resume on changes to `control`.
+    /// Its current value applies right away.
+    pub fn with_control(
+        mut self,
+        mut control: tokio::sync::watch::Receiver<Control>,
+    ) -> BoardWorker {
+        let current = control.borrow_and_update().clone();
+        self.configure(current.subscription);
+        self.paused = current.paused;
+        if let Some(board) = current.board {
+            self.board = board;
         }
+        self.control = Some(control);
+        self
+    }
+
+    /// Apply new settings. The board can't change.
+    fn configure(&mut self, cfg: Subscription) {
+        if (cfg.refresh_rate_ms, cfg.adaptive_refresh)
+            != (self.cfg.refresh_rate_ms, self.cfg.adaptive_refresh)
+        {
+            self.interval = PollInterval::new(cfg.refresh_rate_ms, cfg.adaptive_refresh);
+        }
+        self.cfg = Subscription {
+            board_name: self.cfg.board_name.clone(),
+            thread_no: self.cfg.thread_no,
+            ..cfg
+        };
+    }
+
+    /// Apply the latest settings, if they changed. Returns whether the worker is paused.
+    fn apply_control(&mut self) -> bool {
+        let Some(control) = self.control.as_mut() else {
+            return self.paused;
+        };
+        if control.has_changed().unwrap_or(false) {
+            let current = control.borrow_and_update().clone();
+            info!(
+                "Applying new settings to worker: {}, paused: {}",
+                self.board.name(),
+                current.paused
+            );
+            self.configure(current.subscription);
+            self.paused = current.paused;
+            if let Some(board) = current.board {
+                self.board = board;
+            }
+        }
+        self.paused
+    }
+
+    /// Status updates, sent after every board update.
+    pub fn status(&self) -> tokio::sync::watch::Receiver<WorkerStatus> {
+        self.status.subscribe()
+    }
+
+    /// Resume from the board's checkpoint on startup and save new ones as it runs.
+    pub fn with_checkpoints(mut self, store: Arc<dyn CheckpointStore>) -> BoardWorker {
+        self.checkpoints = Some(store);
+        self
+    }
+
+    /// Keep a checkpoint in `store` after every update and resume from it in `init`,
+    /// before the checkpoint store's, so a worker replacing a panicked one
+    /// picks up the posts made since its last update instead of marking them as seen.
+    pub fn with_restart_checkpoint(mut self, store: MemoryCheckpointStore) -> BoardWorker {
+        self.restart = Some(store);
+        self
     }
 
     pub async fn new_and_run(
@@ -101,179 +814,1410 @@ impl BoardWorker {
         board: Board,
         new_posts_tx: tokio::sync::mpsc::Sender<Event>,
         kill: Option<tokio::sync::oneshot::Receiver<()>>,
+        checkpoints: Option<Arc<dyn CheckpointStore>>,
     ) -> Result<(), Error> {
         let mut worker = BoardWorker::new(api, cfg, board, new_posts_tx, kill);
+        if let Some(store) = checkpoints {
+            worker = worker.with_checkpoints(store);
+        }
         worker.run().await
     }
 
+    /// Mark everything currently on the board as seen.
+    /// The catalog includes the last replies of every thread,
+    /// so the highest post number per thread is known without fetching each one.
+    /// With a checkpoint, only posts up to it are marked as seen instead:
+    /// threads with newer posts are fetched on the first update, and threads
+    /// that dropped off the board since are backfilled here.
     pub async fn init(&mut self) -> Result<(), Error> {
-        self.api.get_threads(self.board.name()).await.map(|pages| {
-            self.update_cache(&pages);
-        })
+        let checkpoint = self.load_checkpoint();
+        if let Some(checkpoint) = &checkpoint {
+            self.seq = checkpoint.seq;
+        }
+        let catalog = self.api.get_catalog(self.board.name()).await?;
+        for (page, thread) in catalog
+            .iter()
+            .flat_map(|page| page.threads.iter().map(move |t| (page.page, t)))
+        {
+            let last_post_no = thread
+                .last_replies
+                .iter()
+                .flatten()
+                .map(|post| post.no)
+                .fold(thread.no, i32::max);
+            self.cache.init_post_no = self.cache.init_post_no.max(last_post_no);
+            let mut cache = ThreadCache {
+                last_post_no,
+                flags: Some(ThreadFlags::new(thread)),
+                page: Some(page),
+                ..ThreadCache::new(thread.no, thread.last_modified.unwrap_or(0))
+            };
+            if let Some(checkpoint) = &checkpoint {
+                cache.last_post_no = checkpoint.thread_last_post_no(thread.no);
+                if cache.last_post_no < last_post_no {
+                    cache.last_modified = 0;
+                }
+            }
+            self.cache.threads.insert(thread.no, cache);
+        }
+        match checkpoint {
+            Some(checkpoint) => {
+                self.cache.init_post_no = checkpoint.last_post_no;
+                self.backfill_removed(&checkpoint).await;
+            }
+            None if self.cfg.backfill != Backfill::None => self.backfill().await,
+            None => {}
+        }
+        self.flush().await;
+        self.save_restart_checkpoint();
+        Ok(())
     }
 
-    pub async fn run(&mut self) -> Result<(), Error> {
-        self.init().await?;
-        loop {
-            if let Some(ref mut rx) = self.kill {
-                match rx.try_recv() {
-                    Ok(_) => {
-                        info!(
-                            "Received kill signal, stopping worker: {}",
-                            self.board.name()
+    /// Send every post of the live threads, and of the archived ones with
+    /// `Backfill::LiveAndArchived`, as historical events in post number order.
+    /// Posts are sorted before sending, so they are all held in memory until then.
+    async fn backfill(&mut self) {
+        let board = self.board.clone();
+        let board_name = board.name().to_string();
+        let mut nos = self.cache.threads.keys().copied().collect::<Vec<_>>();
+        if self.cfg.backfill == Backfill::LiveAndArchived {
+            match self.api.get_board_archive(&board).await {
+                Ok(archive) => nos.extend(archive.threads),
+                Err(Error::NotArchived(_)) => debug!("{} has no archive to backfill", board_name),
+                Err(e) => error!("Error fetching archive of {}: {:?}", board_name, e),
+            }
+        }
+        nos.sort();
+        nos.dedup();
+        info!("Backfilling {} threads of {}", nos.len(), board_name);
+        let mut events = vec![];
+        for no in nos {
+            let thread = match self.api.get_thread(&board_name, no).await {
+                Ok(thread) => thread,
+                Err(Error::StatusCode(code)) if code == "404" => {
+                    debug!("Backfilled thread {} is gone", no);
+                    continue;
+                }
+                Err(e) => {
+                    error!("Error backfilling thread {}: {:?}", no, e);
+                    continue;
+                }
+            };
+            // Posts made since the catalog was fetched are sent now, not again when live.
+            if let Some(cache) = self.cache.threads.get_mut(&no) {
+                cache.last_post_no = thread
+                    .posts
+                    .iter()
+                    .map(|post| post.no)
+                    .fold(cache.last_post_no, i32::max);
+                if self.cfg.track_deletions {
+                    cache.last_thread = Some(thread.clone());
+                }
+            }
+            let fetched_at_ms = self.api.clock().now_ms();
+            events.extend(
+                thread
+                    .posts
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, post)| {
+                        self.cfg
+                            .filter
+                            .as_ref()
+                            .is_none_or(|f| f.matches(post, thread.op()))
+                    })
+                    .map(|(ordinal, post)| {
+                        let envelope = Envelope::new(
+                            board.clone(),
+                            post,
+                            thread.op(),
+                            Some(ordinal),
+                            fetched_at_ms,
                         );
-                        return Ok(());
+                        Event::historical(board_name.clone(), Arc::new(post.clone()))
+                            .with_envelope(envelope)
+                    }),
+            );
+        }
+        events.sort_by_key(|event| event.post().map(|post| post.no));
+        for event in events {
+            self.send(event).await;
+        }
+    }
+
+    /// Emit posts made since the checkpoint in threads that are no longer on the board.
+    async fn backfill_removed(&mut self, checkpoint: &Checkpoint) {
+        let mut removed = checkpoint
+            .threads
+            .iter()
+            .filter(|(no, _)| !self.cache.threads.contains_key(no))
+            .map(|(no, last_post_no)| (*no, *last_post_no))
+            .collect::<Vec<_>>();
+        removed.sort();
+        let board = self.board.clone();
+        for (no, last_post_no) in removed {
+            match self.api.get_thread(self.board.name(), no).await {
+                Ok(thread) => {
+                    let fetched_at_ms = self.api.clock().now_ms();
+                    let events = (0..thread.posts.len())
+                        .filter(|i| {
+                            let p = &thread.posts[*i];
+                            p.no > last_post_no
+                                && self
+                                    .cfg
+                                    .filter
+                                    .as_ref()
+                                    .is_none_or(|f| f.matches(p, thread.op()))
+                        })
+                        .map(|i| Event::fetched(&board, &thread, i, fetched_at_ms))
+                        .collect::<Vec<_>>();
+                    for event in events {
+                        self.send(event).await;
                     }
-                    Err(tokio::sync::oneshot::error::TryRecvError::Closed) => {
-                        error!(
-                            "Kill channel closed, stopping worker: {}",
-                            self.board.name()
-                        );
+                }
+                Err(Error::StatusCode(code)) if code == "404" => {
+                    debug!("Checkpointed thread {} is gone", no)
+                }
+                Err(e) => error!("Error backfilling thread {}: {:?}", no, e),
+            }
+        }
+    }
+
+    /// The current high-water marks of the board.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            seq: self.seq,
+            ..self.cache.checkpoint(self.board.name())
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn cache(&self) -> &BoardCache {
+        &self.cache
+    }
+
+    pub fn into_cache(self) -> BoardCache {
+        self.cache
+    }
+
+    /// The restart checkpoint if there is one, else the store's.
+    fn load_checkpoint(&self) -> Option<Checkpoint> {
+        let restart = self
+            .restart
+            .as_ref()
+            .map(|store| store as &dyn CheckpointStore);
+        [restart, self.checkpoints.as_deref()]
+            .iter()
+            .flatten()
+            .find_map(|store| match store.load(self.board.name()) {
+                Ok(checkpoint) => checkpoint,
+                Err(e) => {
+                    error!(
+                        "Error loading checkpoint for {}: {:?}",
+                        self.board.name(),
+                        e
+                    );
+                    None
+                }
+            })
+    }
+
+    fn save_restart_checkpoint(&self) {
+        let Some(store) = &self.restart else {
+            return;
+        };
+        if let Err(e) = store.save(&self.checkpoint()) {
+            error!(
+                "Error saving restart checkpoint for {}: {:?}",
+                self.board.name(),
+                e
+            );
+        }
+    }
+
+    fn save_checkpoint(&mut self) {
+        let Some(store) = &self.checkpoints else {
+            return;
+        };
+        debug!("Saving checkpoint for {}", self.board.name());
+        if let Err(e) = store.save(&self.checkpoint()) {
+            error!("Error saving checkpoint for {}: {:?}", self.board.name(), e);
+        }
+        self.last_checkpoint_ms = self.api.clock().now_ms();
+    }
+
+    /// Run until killed, or until a board update fails.
+    /// Running it again after a failure continues from the current cache.
+    pub async fn run(&mut self) -> Result<(), Error> {
+        if !self.initialized {
+            self.init().await?;
+            self.initialized = true;
+        }
+        loop {
+            if self.apply_control() {
+                self.status
+                    .send_modify(|status| status.state = WorkerState::Paused);
+                while self.apply_control() {
+                    let wait =
+                        tokio::time::Duration::from_millis(self.interval.interval_ms() as u64);
+                    let clock = self.api.clock().as_ref();
+                    let changed = control_changed(&mut self.control, clock, wait);
+                    if until_killed(&mut self.kill, clock, tokio::time::Duration::ZERO, changed)
+                        .await
+                        .1
+                    {
+                        self.stop().await;
                         return Ok(());
                     }
-                    _ => {}
                 }
             }
-            self.update_board()
+            let mut kill = self.kill.take();
+            let grace =
+                tokio::time::Duration::from_millis(self.cfg.shutdown_timeout_ms.max(0) as u64);
+            let clock = self.api.clock().clone();
+            let (result, killed) =
+                until_killed(&mut kill, clock.as_ref(), grace, self.update_board()).await;
+            self.kill = kill;
+            if killed {
+                if result.is_none() {
+                    error!("Cancelled in-flight fetches of board {}", self.board.name());
+                }
+                self.stop().await;
+                return Ok(());
+            }
+            if let Some(Err(e)) = result {
+                error!("Error updating board {}: {:?}", self.board.name(), e);
+                self.save_checkpoint();
+                return Err(e);
+            }
+            self.save_restart_checkpoint();
+            if self.api.clock().now_ms() - self.last_checkpoint_ms
+                >= self.cfg.checkpoint_interval_ms
+            {
+                self.save_checkpoint();
+            }
+            // New settings cut the sleep short.
+            let clock = self.api.clock().as_ref();
+            let sleep = control_changed(
+                &mut self.control,
+                clock,
+                tokio::time::Duration::from_millis(self.interval.interval_ms() as u64),
+            );
+            if until_killed(&mut self.kill, clock, tokio::time::Duration::ZERO, sleep)
                 .await
-                .map_err(|e| error!("{:?}", e))
-                .unwrap();
-            tokio::time::sleep(tokio::time::Duration::from_millis(
-                self.cfg.refresh_rate_ms as u64,
-            ))
-            .await;
+                .1
+            {
+                self.stop().await;
+                return Ok(());
+            }
         }
     }
 
+    async fn stop(&mut self) {
+        info!(
+            "Received kill signal, stopping worker: {}",
+            self.board.name()
+        );
+        // Events of a cancelled cycle are already marked as seen.
+        self.flush().await;
+        self.save_checkpoint();
+    }
+
     /// A full board update cycle
-    /// 1. Fetch all threads and update the local cache, returning new and modified threads
-    /// 2. Fetch each modified thread, entirely, in parallel
-    /// 3. Filter new posts and send them to the main thread
-    /// 4. Send new cache object to the main thread for storage
+    /// 1. Fetch all threads and diff them against the local cache
+    /// 2. Send page changes, and classify removed threads as archived or pruned,
+    ///    looking up those that 404 in the archive, fetched once
+    /// 3. Fetch each new or modified thread, entirely, in parallel
+    /// 4. Send posts above the thread's highest seen post number, and changes to the OP's flags
+    /// 5. Record the new highest post numbers, or revert threads that failed to fetch
+    /// 6. Send the events buffered for the subscription's `EventOrder`, sorted
+    ///
+    /// When polling the catalog, threads whose new posts are all in the catalog's
+    /// preview of their last replies are sent from it, without fetching them.
     async fn update_board(&mut self) -> Result<(), Error> {
         debug!("Performing full board update: {}", self.board.name());
-        let now = chrono::Utc::now().timestamp();
-        let last_update_sec = self.cache.last_update_sec;
+        let now = self.api.clock().now().timestamp();
+        let diff = if self.cfg.poll_catalog {
+            let catalog = self.api.get_catalog(self.board.name()).await?;
+            self.update_cache(&Self::page_threads(
+                catalog.iter().map(|page| (page.page, &page.threads)),
+            ))
+        } else {
+            let pages = self.api.get_threads(self.board.name()).await?;
+            self.update_cache(&Self::page_threads(
+                pages.iter().map(|page| (page.page, &page.threads)),
+            ))
+        };
+        self.interval
+            .record(diff.modified.len() + diff.removed.len());
+        self.activity
+            .store(self.interval.activity(), Ordering::Relaxed);
+
+        let moved = if self.cfg.track_pages {
+            diff.moved
+        } else {
+            vec![]
+        };
+        for (no, from, to) in moved {
+            self.send(Event::thread_changed(
+                self.board.name().to_string(),
+                no,
+                ThreadChange::PageChanged { from, to },
+            ))
+            .await;
+        }
+
+        let mut removals = vec![];
+        let mut tasks = AbortOnDrop(vec![]);
+        for no in diff.removed {
+            let api = self.api.clone();
+            let board = self.board.clone();
+            let removal = tokio::spawn(async move { (no, api.get_thread(board.name(), no).await) });
+            tasks.0.push(removal.abort_handle());
+            removals.push(removal);
+        }
+
         let mut rxs = vec![];
-        for modified_thread in self
-            .api
-            .get_threads(self.board.name())
-            .await
-            .map(|pages| self.update_cache(&pages))?
-        {
+        for modified_thread in diff.modified {
+            if self.cfg.poll_catalog
+                && !self.cfg.track_deletions
+                && self.update_from_preview(&modified_thread).await
+            {
+                continue;
+            }
             let api = self.api.clone();
-            let board_name = self.board.name().to_string();
-            let new_posts_chan = self.events_chan.clone();
-            let cache = self
-                .cache
-                .threads
-                .get(&modified_thread.no)
-                .unwrap_or(&ThreadCache::new(modified_thread.no, last_update_sec))
-                .clone();
+            let board = self.board.clone();
+            let cache = self.cache.get_or_insert(modified_thread.no).clone();
+            let track_deletions = self.cfg.track_deletions;
+            let filter = self.cfg.filter.clone();
             let (tx, rx) = tokio::sync::oneshot::channel();
             rxs.push(rx);
-            tokio::spawn(async move {
-                match api.get_thread(&board_name, cache.no).await {
+            let fetch = tokio::spawn(async move {
+                let board_name = board.name().to_string();
+                let fetch = match api.get_thread(&board_name, cache.no).await {
                     Ok(thread) => {
-                        for new_post in thread
+                        let fetched_at_ms = api.clock().now_ms();
+                        let mut events = vec![];
+                        let mut last_post_no = cache.last_post_no;
+                        for (ordinal, new_post) in thread
                             .posts
                             .iter()
-                            .filter(|post| {
-                                post.time.map_or(false, |t| t > cache.prev_last_modified)
-                            })
-                            .collect::<Vec<_>>()
+                            .enumerate()
+                            .filter(|(_, post)| post.no > cache.last_post_no)
                         {
-                            if let Err(e) = new_posts_chan.send(Event::new(board_name.clone(), Arc::new(new_post.clone()))).await {
-                                error!("Error sending new post: {:?}", e);
+                            last_post_no = last_post_no.max(new_post.no);
+                            events.push(Event::fetched(&board, &thread, ordinal, fetched_at_ms));
+                        }
+                        if let Some(prev) = &cache.last_thread {
+                            events.extend(Event::deletions(&board_name, prev, &thread));
+                        }
+                        let flags = thread.op().map(ThreadFlags::new);
+                        if let (Some(prev), Some(next)) = (cache.flags, flags) {
+                            for change in prev.changes(&next) {
+                                events.push(Event::thread_changed(
+                                    board_name.clone(),
+                                    cache.no,
+                                    change,
+                                ));
                             }
                         }
-                        if let Err(e) = tx.send(None) {
-                            error!("Error sending thread no: {:?}", e);
+                        if let Some(filter) = &filter {
+                            events.retain(|event| filter.matches_event(event, thread.op()));
+                        }
+                        ThreadFetch::Fetched {
+                            no: cache.no,
+                            events,
+                            last_post_no,
+                            flags,
+                            thread: track_deletions.then_some(thread),
                         }
                     }
                     Err(e) => {
                         error!("Error fetching thread: {:?}", e);
-                        if let Err(e) = tx.send(Some(cache.no)) {
-                            error!("Error sending thread no: {:?}", e);
-                        }
+                        ThreadFetch::Failed { no: cache.no }
                     }
+                };
+                if tx.send(fetch).is_err() {
+                    error!("Error sending fetch result for thread: {}", cache.no);
                 }
             });
+            tasks.0.push(fetch.abort_handle());
         }
         debug!(
             "Waiting for tasks to complete, board: {}",
             self.board.name()
         );
-        futures::future::join_all(rxs)
-            .await
+        // Results are applied as they arrive, so a cancelled update keeps finished fetches.
+        let mut fetches = rxs
             .into_iter()
-            .for_each(|res| match res {
-                Ok(Some(thread_no)) if thread_no >= 0 => {
-                    if let Some(entry) = self.cache.threads.get_mut(&thread_no) {
-                        info!("Reverting thread: {}, trying again later", thread_no);
+            .collect::<futures::stream::FuturesUnordered<_>>();
+        while let Some(res) = fetches.next().await {
+            match res {
+                Ok(ThreadFetch::Fetched {
+                    no,
+                    events,
+                    last_post_no,
+                    flags,
+                    thread,
+                }) => {
+                    for event in events {
+                        self.send(event).await;
+                    }
+                    if let Some(entry) = self.cache.threads.get_mut(&no) {
+                        entry.last_post_no = last_post_no;
+                        entry.flags = flags.or(entry.flags);
+                        entry.last_thread = thread;
+                    }
+                }
+                Ok(ThreadFetch::Failed { no }) => {
+                    if let Some(entry) = self.cache.threads.get_mut(&no) {
+                        info!("Reverting thread: {}, trying again later", no);
                         entry.last_modified = entry.prev_last_modified;
                     }
                 }
-                _ => {}
-            });
+                Err(_) => {}
+            }
+        }
+        let mut gone = vec![];
+        for removal in futures::future::join_all(removals).await {
+            let Ok((no, fetched)) = removal else {
+                continue;
+            };
+            match fetched.map(|thread| thread.status()) {
+                Ok(ThreadStatus::Live) => {
+                    debug!("Thread {} missing from threads.json but still live", no);
+                }
+                Ok(status) => self.send_removed(no, status).await,
+                Err(Error::StatusCode(code)) if code == "404" => gone.push(no),
+                Err(e) => error!("Error classifying removed thread {}: {:?}", no, e),
+            }
+        }
+        if !gone.is_empty() {
+            // One archive fetch classifies every thread that 404'd this cycle.
+            let statuses = match self.api.get_board_archive(&self.board).await {
+                Ok(archive) => gone.iter().map(|no| (*no, archive.status(*no))).collect(),
+                Err(Error::NotArchived(_)) => {
+                    gone.iter().map(|no| (*no, ThreadStatus::Removed)).collect()
+                }
+                Err(e) => {
+                    error!("Error fetching archive of {}: {:?}", self.board.name(), e);
+                    vec![]
+                }
+            };
+            for (no, status) in statuses {
+                self.send_removed(no, status).await;
+            }
+        }
+        self.flush().await;
         self.cache.last_update_sec = now;
+        let threads = self.cache.threads.len();
+        let refresh_rate_ms = self.interval.interval_ms();
+        self.status.send_modify(|status| {
+            status.state = WorkerState::Running;
+            status.refresh_rate_ms = refresh_rate_ms;
+            status.last_update_sec = now;
+            status.threads = threads;
+        });
         Ok(())
     }
 
+    async fn send_removed(&mut self, no: i32, status: ThreadStatus) {
+        self.send(Event::thread_changed(
+            self.board.name().to_string(),
+            no,
+            ThreadChange::Removed(status),
+        ))
+        .await;
+    }
+
+    /// Number and send the event, or buffer it until the end of the cycle
+    /// if the subscription sorts its events.
+    async fn send(&mut self, event: Event) {
+        if self.cfg.order == EventOrder::Arrival {
+            self.deliver(event).await;
+        } else {
+            self.pending.push(event);
+        }
+    }
+
+    /// Sort and send the buffered events.
+    /// Events about threads rather than posts are sent first.
+    async fn flush(&mut self) {
+        let mut pending = std::mem::take(&mut self.pending);
+        match self.cfg.order {
+            EventOrder::Arrival => {}
+            EventOrder::PostNumber => pending.sort_by_key(|event| event.post().map(|p| p.no)),
+            EventOrder::PostTime => {
+                pending.sort_by_key(|event| event.post().map(|p| (p.time, p.no)))
+            }
+        }
+        for event in pending {
+            self.deliver(event).await;
+        }
+    }
+
+    async fn deliver(&mut self, mut event: Event) {
+        self.seq += 1;
+        event.stamp(self.seq, self.api.clock().now_ms());
+        if let Err(e) = self.events_chan.send(event).await {
+            error!("Error sending event: {:?}", e);
+        }
+    }
+
+    /// Send a thread's new posts and flag changes from the OP's catalog entry,
+    /// if its preview of the last replies has every post made since the last update.
+    /// Returns false if the thread has to be fetched instead.
+    async fn update_from_preview(&mut self, op: &Post) -> bool {
+        let board = self.board.clone();
+        let board_name = board.name().to_string();
+        let cache = self.cache.get_or_insert(op.no);
+        let last_post_no = cache.last_post_no;
+        let preview = op.last_replies.as_deref().unwrap_or_default();
+        // The preview has the last replies, so it has every new one
+        // if it has all of them, or reaches back to one already seen.
+        let complete = op.replies.unwrap_or(0) as usize <= preview.len()
+            || preview
+                .first()
+                .is_some_and(|first| first.no <= last_post_no);
+        if !complete {
+            return false;
+        }
+        let fetched_at_ms = self.api.clock().now_ms();
+        let envelope = |post: &Post, ordinal| {
+            Envelope::new(board.clone(), post, Some(op), ordinal, fetched_at_ms)
+        };
+        let mut events = vec![];
+        if op.no > last_post_no {
+            let op = Post {
+                last_replies: None,
+                ..op.clone()
+            };
+            let envelope = envelope(&op, Some(0));
+            events.push(Event::new(board_name.clone(), Arc::new(op)).with_envelope(envelope));
+        }
+        // The last post of the preview is the thread's last reply.
+        let replies = op.replies.map(|replies| replies.max(0) as usize);
+        for (i, post) in preview.iter().enumerate() {
+            if post.no <= last_post_no {
+                continue;
+            }
+            let ordinal = replies.and_then(|replies| replies.checked_sub(preview.len() - 1 - i));
+            events.push(
+                Event::new(board_name.clone(), Arc::new(post.clone()))
+                    .with_envelope(envelope(post, ordinal)),
+            );
+        }
+        let flags = ThreadFlags::new(op);
+        if let Some(prev) = cache.flags {
+            for change in prev.changes(&flags) {
+                events.push(Event::thread_changed(board_name.clone(), op.no, change));
+            }
+        }
+        cache.flags = Some(flags);
+        cache.last_post_no = preview
+            .iter()
+            .map(|post| post.no)
+            .fold(last_post_no.max(op.no), i32::max);
+        if let Some(filter) = &self.cfg.filter {
+            events.retain(|event| filter.matches_event(event, Some(op)));
+        }
+        for event in events {
+            self.send(event).await;
+        }
+        true
+    }
+
+    /// Threads of every page, with their page number.
+    fn page_threads<'a>(pages: impl Iterator<Item = (i32, &'a Vec<Post>)>) -> Vec<(i32, &'a Post)> {
+        pages
+            .flat_map(|(page, threads)| threads.iter().map(move |thread| (page, thread)))
+            .collect()
+    }
+
     /// Update the local cache with new and modified threads
     /// 1. Remove deleted threads
     /// 2. Add new threads
-    /// 3. Update modified threads
+    /// 3. Update modified threads and page positions
     /// 4. Sort modified threads by last_modified
-    /// 5. Return modified threads
-    fn update_cache(&mut self, pages: &[ThreadPage]) -> Vec<Post> {
-        let n_threads: usize = pages.iter().map(|page| page.threads.len()).sum();
+    /// 5. Return the differences
+    fn update_cache(&mut self, threads: &[(i32, &Post)]) -> CacheDiff {
         debug!(
             "Updating cache for board: {}, threads: {}",
             self.board.name(),
-            n_threads,
+            threads.len(),
         );
-        self.cache.threads.retain(|k, _| {
-            pages
-                .iter()
-                .any(|page| page.threads.iter().any(|t| t.no == *k))
-        });
-        let mut modified_threads = vec![];
-        for page in pages {
-            for thread in &page.threads {
-                let cache = self
-                    .cache
-                    .threads
-                    .entry(thread.no)
-                    .or_insert(ThreadCache::new(thread.no, 0));
-                let thread_last_modified = thread.last_modified.unwrap_or(0);
-                if cache.last_modified < thread_last_modified {
-                    modified_threads.push(thread.clone());
-                    cache.prev_last_modified = cache.last_modified;
-                    cache.last_modified = thread_last_modified;
-                }
+        let mut diff = CacheDiff {
+            removed: self
+                .cache
+                .threads
+                .keys()
+                .filter(|k| !threads.iter().any(|(_, t)| t.no == **k))
+                .copied()
+                .collect(),
+            ..Default::default()
+        };
+        for no in &diff.removed {
+            self.cache.remove(*no);
+        }
+        for (page, thread) in threads {
+            let cache = self.cache.get_or_insert(thread.no);
+            match cache.page {
+                Some(from) if from != *page => diff.moved.push((thread.no, from, *page)),
+                _ => {}
+            }
+            cache.page = Some(*page);
+            let thread_last_modified = thread.last_modified.unwrap_or(0);
+            if cache.last_modified < thread_last_modified {
+                diff.modified.push((*thread).clone());
+                cache.prev_last_modified = cache.last_modified;
+                cache.last_modified = thread_last_modified;
             }
         }
-        modified_threads.sort_by(|a, b| a.last_modified.cmp(&b.last_modified));
-        modified_threads
+        diff.modified.sort_by_key(|t| t.last_modified);
+        diff
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rchan_api::client::Client;
-    use rchan_types::board::Cooldowns;
+    use rchan_api::{
+        client::{Client, Config},
+        endpoint::Endpoint,
+        response::ClientResponse,
+        transport::ScriptedTransport,
+    };
+    use rchan_types::{
+        board::Cooldowns,
+        catalog::CatalogPage,
+        post::{Thread, ThreadPage},
+    };
     use std::sync::Arc;
 
+    fn post(no: i32, resto: i32, time: i64) -> Post {
+        Post {
+            no,
+            resto: Some(resto),
+            time: Some(time),
+            ..Default::default()
+        }
+    }
+
+    fn op(no: i32, last_modified: i64, last_replies: &[i32]) -> Post {
+        Post {
+            last_modified: Some(last_modified),
+            last_replies: Some(last_replies.iter().map(|r| post(*r, no, 0)).collect()),
+            ..post(no, 0, 0)
+        }
+    }
+
+    fn threads(threads: &[(i32, i64)]) -> ClientResponse {
+        ClientResponse::Threads(Arc::new(vec![ThreadPage {
+            page: 1,
+            threads: threads.iter().map(|(no, lm)| op(*no, *lm, &[])).collect(),
+        }]))
+    }
+
+    fn thread(posts: &[(i32, i64)]) -> ClientResponse {
+        let no = posts[0].0;
+        ClientResponse::Thread(Arc::new(Thread {
+            posts: posts
+                .iter()
+                .map(|(p, time)| post(*p, if *p == no { 0 } else { no }, *time))
+                .collect(),
+        }))
+    }
+
+    fn scripted_worker(
+        transport: Arc<ScriptedTransport>,
+        catalog: Vec<Post>,
+    ) -> (BoardWorker, tokio::sync::mpsc::Receiver<Event>) {
+        transport.push(
+            Endpoint::Catalog("g".to_string()),
+            ClientResponse::Catalog(Arc::new(vec![CatalogPage {
+                page: 1,
+                threads: catalog,
+            }])),
+        );
+        let client = Arc::new(Client::with_transport(
+            Some(Config::new(None, Some(0))),
+            transport,
+        ));
+        let board = Board {
+            board: "g".to_string(),
+            per_page: 15,
+            pages: 10,
+            ..Default::default()
+        };
+        let (tx, rx) = tokio::sync::mpsc::channel(100);
+        let cfg = Subscription::new("g".to_string(), Some(10));
+        (BoardWorker::new(client, cfg, board, tx, None), rx)
+    }
+
+    fn received(rx: &mut tokio::sync::mpsc::Receiver<Event>) -> Vec<i32> {
+        let mut nos = vec![];
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                Event::NewPost(NewPost { post, .. }) | Event::NewThread(NewThread { post, .. }) => {
+                    nos.push(post.no)
+                }
+                _ => {}
+            }
+        }
+        nos.sort();
+        nos
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_same_second_posts() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        transport.push(Endpoint::Threads("g".to_string()), threads(&[(1, 105)]));
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            thread(&[(1, 90), (2, 100), (3, 100), (4, 105)]),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        assert_eq!(received(&mut rx), vec![3, 4]);
+
+        worker.update_board().await.unwrap();
+        assert!(received(&mut rx).is_empty());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_scheduled_requests() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (worker, mut rx) =
+            scripted_worker(transport.clone(), vec![op(1, 100, &[]), op(2, 100, &[])]);
+        let scheduler = Scheduler::new(1);
+        let mut worker = worker.with_scheduler(scheduler.clone());
+        transport.push(
+            Endpoint::Threads("g".to_string()),
+            threads(&[(1, 105), (2, 105)]),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            thread(&[(1, 90), (3, 105)]),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 2),
+            thread(&[(2, 90), (4, 105)]),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        assert_eq!(received(&mut rx), vec![3, 4]);
+        assert_eq!(scheduler.pending(), 0);
+        assert_eq!(transport.requests().len(), 4);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_failed_fetch() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        let threads_endpoint = Endpoint::Threads("g".to_string());
+        let thread_endpoint = Endpoint::Thread("g".to_string(), 1);
+        transport.push(threads_endpoint.clone(), threads(&[(1, 105)]));
+        transport.push(threads_endpoint.clone(), threads(&[(1, 110)]));
+        transport.push(
+            thread_endpoint.clone(),
+            thread(&[(1, 90), (2, 100), (3, 105)]),
+        );
+        transport.push_status(thread_endpoint.clone(), 500);
+        transport.push(
+            thread_endpoint.clone(),
+            thread(&[(1, 90), (2, 100), (3, 105), (4, 110)]),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        assert_eq!(received(&mut rx), vec![3]);
+
+        worker.update_board().await.unwrap();
+        assert!(received(&mut rx).is_empty());
+
+        worker.update_board().await.unwrap();
+        assert_eq!(received(&mut rx), vec![4]);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_thread_changes() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) =
+            scripted_worker(transport.clone(), vec![op(1, 100, &[2]), op(3, 100, &[])]);
+        worker.cfg.track_pages = true;
+        transport.push(
+            Endpoint::Threads("g".to_string()),
+            ClientResponse::Threads(Arc::new(vec![
+                ThreadPage {
+                    page: 1,
+                    threads: vec![],
+                },
+                ThreadPage {
+                    page: 2,
+                    threads: vec![op(1, 110, &[])],
+                },
+            ])),
+        );
+        let ClientResponse::Thread(closed) = thread(&[(1, 90), (2, 100)]) else {
+            unreachable!()
+        };
+        let mut closed = (*closed).clone();
+        closed.posts[0].closed = Some(1);
+        closed.posts[0].bumplimit = Some(1);
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            ClientResponse::Thread(Arc::new(closed)),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        let mut changes = vec![];
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                Event::ThreadChanged(ThreadChanged { no, change, .. }) => {
+                    changes.push((no, change))
+                }
+                other => panic!("Unexpected event: {:?}", other),
+            }
+        }
+        changes.sort_by_key(|(no, _)| *no);
+        assert_eq!(
+            changes,
+            vec![
+                (1, ThreadChange::PageChanged { from: 1, to: 2 }),
+                (1, ThreadChange::Closed),
+                (1, ThreadChange::BumpLimitReached),
+                (3, ThreadChange::Removed(ThreadStatus::Removed)),
+            ]
+        );
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_removed_threads() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) = scripted_worker(
+            transport.clone(),
+            vec![op(1, 100, &[]), op(3, 100, &[]), op(5, 100, &[])],
+        );
+        worker.board = Arc::new(Board {
+            is_archived: Some(1),
+            ..(*worker.board).clone()
+        });
+        transport.push(Endpoint::Threads("g".to_string()), threads(&[(1, 100)]));
+        transport.push(
+            Endpoint::Archive("g".to_string()),
+            ClientResponse::Archive(Arc::new(vec![3])),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        let mut changes = vec![];
+        while let Ok(Event::ThreadChanged(ThreadChanged { no, change, .. })) = rx.try_recv() {
+            changes.push((no, change));
+        }
+        changes.sort_by_key(|(no, _)| *no);
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    3,
+                    ThreadChange::Removed(ThreadStatus::Archived { archived_on: None })
+                ),
+                (5, ThreadChange::Removed(ThreadStatus::Removed)),
+            ]
+        );
+        // Both threads 404'd, and are classified by a single fetch of the archive.
+        let archive_fetches = transport
+            .requests()
+            .iter()
+            .filter(|endpoint| matches!(endpoint, Endpoint::Archive(_)))
+            .count();
+        assert_eq!(archive_fetches, 1);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_restart_checkpoint() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let store = MemoryCheckpointStore::new();
+        let (worker, _rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        let mut worker = worker.with_restart_checkpoint(store.clone());
+        worker.init().await.unwrap();
+
+        // Reply 3 was made before the worker panicked, its replacement still sends it.
+        let (worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 110, &[2, 3])]);
+        let mut worker = worker.with_restart_checkpoint(store);
+        transport.push(Endpoint::Threads("g".to_string()), threads(&[(1, 110)]));
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            thread(&[(1, 100), (2, 101), (3, 110)]),
+        );
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        assert_eq!(received(&mut rx), vec![3]);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_restart_seq() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let store = MemoryCheckpointStore::new();
+        let (worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        let mut worker = worker.with_restart_checkpoint(store.clone());
+        let threads_endpoint = Endpoint::Threads("g".to_string());
+        let thread_endpoint = Endpoint::Thread("g".to_string(), 1);
+        transport.push(threads_endpoint.clone(), threads(&[(1, 105)]));
+        transport.push(threads_endpoint, threads(&[(1, 110)]));
+        transport.push(
+            thread_endpoint.clone(),
+            thread(&[(1, 90), (2, 100), (3, 105)]),
+        );
+        transport.push(
+            thread_endpoint,
+            thread(&[(1, 90), (2, 100), (3, 105), (4, 110)]),
+        );
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        worker.save_restart_checkpoint();
+        assert_eq!(rx.try_recv().unwrap().seq(), 1);
+
+        // The replacement of a panicked worker numbers its events on from the last one sent.
+        let (worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 105, &[2, 3])]);
+        let mut worker = worker.with_restart_checkpoint(store);
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        let event = rx.try_recv().unwrap();
+        assert_eq!(event.post().unwrap().no, 4);
+        assert_eq!(event.seq(), 2);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_untracked_pages() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) =
+            scripted_worker(transport.clone(), vec![op(3, 100, &[]), op(1, 100, &[])]);
+        transport.push(
+            Endpoint::Threads("g".to_string()),
+            ClientResponse::Threads(Arc::new(vec![
+                ThreadPage {
+                    page: 1,
+                    threads: vec![op(3, 100, &[])],
+                },
+                ThreadPage {
+                    page: 2,
+                    threads: vec![op(1, 100, &[])],
+                },
+            ])),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_deletions() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        worker.cfg.track_deletions = true;
+        let threads_endpoint = Endpoint::Threads("g".to_string());
+        let thread_endpoint = Endpoint::Thread("g".to_string(), 1);
+        transport.push(threads_endpoint.clone(), threads(&[(1, 105)]));
+        transport.push(threads_endpoint.clone(), threads(&[(1, 110)]));
+        transport.push(
+            thread_endpoint.clone(),
+            thread(&[(1, 90), (2, 100), (3, 105)]),
+        );
+        transport.push(thread_endpoint.clone(), thread(&[(1, 90), (3, 105)]));
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        assert_eq!(received(&mut rx), vec![3]);
+
+        worker.update_board().await.unwrap();
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(Event::PostDeleted(PostDeleted {
+                thread_no: 1,
+                post,
+                ..
+            })) if post.no == 2
+        ));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_thread_flags() {
+        let prev = ThreadFlags {
+            sticky: true,
+            closed: true,
+            ..Default::default()
+        };
+        let next = ThreadFlags {
+            image_limit: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            prev.changes(&next),
+            vec![
+                ThreadChange::Reopened,
+                ThreadChange::Unstickied,
+                ThreadChange::ImageLimitReached
+            ]
+        );
+        assert!(next.changes(&next).is_empty());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_thread_reappears() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) =
+            scripted_worker(transport.clone(), vec![op(1, 100, &[2]), op(5, 100, &[])]);
+        let threads_endpoint = Endpoint::Threads("g".to_string());
+        transport.push(threads_endpoint.clone(), threads(&[(5, 100)]));
+        transport.push(
+            threads_endpoint.clone(),
+            threads(&[(1, 110), (5, 100), (7, 120)]),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            thread(&[(1, 90), (2, 100), (3, 105)]),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 7),
+            thread(&[(7, 115), (8, 120)]),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        assert!(received(&mut rx).is_empty());
+
+        worker.update_board().await.unwrap();
+        assert_eq!(received(&mut rx), vec![3, 7, 8]);
+
+        worker.update_board().await.unwrap();
+        assert!(received(&mut rx).is_empty());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_envelope() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        let now = 1_700_000_000;
+        let clock = clock::ManualClock::new(chrono::DateTime::from_timestamp(now, 0).unwrap());
+        worker.api = Arc::new(
+            Client::with_transport(Some(Config::new(None, Some(0))), transport.clone())
+                .with_clock(Arc::new(clock)),
+        );
+        transport.push(Endpoint::Threads("g".to_string()), threads(&[(1, 110)]));
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            ClientResponse::Thread(Arc::new(Thread {
+                posts: vec![
+                    Post {
+                        sub: Some("Rust &amp; Go".to_string()),
+                        ..post(1, 0, now - 60)
+                    },
+                    post(2, 1, now - 30),
+                    post(3, 1, now - 5),
+                ],
+            })),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        let event = rx.try_recv().unwrap();
+        let envelope = event.envelope().unwrap();
+        assert_eq!(envelope.id, "g/3");
+        assert_eq!(envelope.board.name(), "g");
+        assert_eq!(envelope.op_no, 1);
+        assert_eq!(envelope.subject.as_deref(), Some("Rust & Go"));
+        assert_eq!(envelope.ordinal, Some(2));
+        assert_eq!(envelope.fetched_at_ms, now * 1000);
+        assert_eq!(envelope.latency_ms, Some(5000));
+
+        let json = serde_json::to_string(&event).unwrap();
+        let event = serde_json::from_str::<Event>(&json).unwrap();
+        assert_eq!(event.envelope().unwrap().id, "g/3");
+        assert_eq!(event.seq(), 1);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_poll_catalog() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        worker.cfg.poll_catalog = true;
+        // Thread 1 has all of its new replies in the preview, thread 5 does not.
+        transport.push(
+            Endpoint::Catalog("g".to_string()),
+            ClientResponse::Catalog(Arc::new(vec![CatalogPage {
+                page: 1,
+                threads: vec![
+                    Post {
+                        replies: Some(3),
+                        ..op(1, 110, &[2, 3, 4])
+                    },
+                    Post {
+                        replies: Some(3),
+                        ..op(5, 120, &[7, 8])
+                    },
+                ],
+            }])),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 5),
+            thread(&[(5, 115), (6, 116), (7, 117), (8, 120)]),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        assert_eq!(received(&mut rx), vec![3, 4, 5, 6, 7, 8]);
+        assert!(!transport
+            .requests()
+            .contains(&Endpoint::Thread("g".to_string(), 1)));
+
+        worker.update_board().await.unwrap();
+        assert!(received(&mut rx).is_empty());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_backfill() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        worker.cfg.backfill = Backfill::LiveAndArchived;
+        worker.board = Arc::new(Board {
+            is_archived: Some(1),
+            ..(*worker.board).clone()
+        });
+        transport.push(
+            Endpoint::Archive("g".to_string()),
+            ClientResponse::Archive(Arc::new(vec![3])),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 3),
+            thread(&[(3, 90), (4, 95)]),
+        );
+        // Reply 5 was made after the catalog was fetched.
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            thread(&[(1, 80), (2, 99), (5, 100)]),
+        );
+        transport.push(Endpoint::Threads("g".to_string()), threads(&[(1, 100)]));
+
+        worker.init().await.unwrap();
+        let mut nos = vec![];
+        while let Ok(event) = rx.try_recv() {
+            match event {
+                Event::NewPost(NewPost {
+                    post,
+                    historical: true,
+                    ..
+                })
+                | Event::NewThread(NewThread {
+                    post,
+                    historical: true,
+                    ..
+                }) => nos.push(post.no),
+                event => panic!("Unexpected event: {:?}", event),
+            }
+        }
+        assert_eq!(nos, vec![1, 2, 3, 4, 5]);
+
+        worker.update_board().await.unwrap();
+        assert!(received(&mut rx).is_empty());
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_backfill_not_archived() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        worker.cfg.backfill = Backfill::LiveAndArchived;
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            thread(&[(1, 80), (2, 99)]),
+        );
+
+        worker.init().await.unwrap();
+        assert_eq!(received(&mut rx), vec![1, 2]);
+        assert!(!transport
+            .requests()
+            .contains(&Endpoint::Archive("g".to_string())));
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_ordered_delivery() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, mut rx) =
+            scripted_worker(transport.clone(), vec![op(1, 100, &[2]), op(5, 100, &[6])]);
+        worker.cfg.order = EventOrder::PostNumber;
+        transport.push(
+            Endpoint::Threads("g".to_string()),
+            threads(&[(1, 110), (5, 110)]),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            thread(&[(1, 100), (2, 101), (7, 107), (9, 109)]),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 5),
+            thread(&[(5, 105), (6, 106), (8, 108)]),
+        );
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        let events = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|event| (event.post().unwrap().no, event.seq()))
+            .collect::<Vec<_>>();
+        assert_eq!(events, vec![(7, 1), (8, 2), (9, 3)]);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test(start_paused = true)]
+    async fn test_poll_under_paused_time() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, _rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[])]);
+        transport.push(Endpoint::Threads("g".to_string()), threads(&[(1, 100)]));
+        let clock: Arc<dyn Clock> = Arc::new(clock::TokioClock::new());
+        worker.api = Arc::new(
+            Client::with_transport(Some(Config::new(None, Some(0))), transport.clone())
+                .with_clock(clock.clone()),
+        );
+        worker.interval = PollInterval::new(10_000, None);
+        let (kill, kill_rx) = tokio::sync::oneshot::channel();
+        worker.kill = Some(kill_rx);
+        let status = worker.status();
+        let start = clock.now().timestamp();
+
+        let handle = tokio::spawn(async move { worker.run().await });
+        clock.sleep(std::time::Duration::from_secs(6 * 3600)).await;
+        kill.send(()).unwrap();
+        handle.await.unwrap().unwrap();
+        let polls = transport
+            .requests()
+            .iter()
+            .filter(|endpoint| matches!(endpoint, Endpoint::Threads(_)))
+            .count();
+        assert!((2160..=2161).contains(&polls), "{}", polls);
+        assert!(status.borrow().last_update_sec - start >= 6 * 3600 - 10);
+    }
+
+    #[test]
+    fn test_poll_interval() {
+        let mut fixed = PollInterval::new(10_000, None);
+        fixed.record(0);
+        assert_eq!(fixed.interval_ms(), 10_000);
+
+        let range = RefreshRange {
+            min_ms: 2_000,
+            max_ms: 60_000,
+        };
+        let mut adaptive = PollInterval::new(10_000, Some(range));
+        adaptive.record(0);
+        adaptive.record(0);
+        assert_eq!(adaptive.interval_ms(), 22_500);
+        for _ in 0..10 {
+            adaptive.record(0);
+        }
+        assert_eq!(adaptive.interval_ms(), 60_000);
+        for _ in 0..10 {
+            adaptive.record(5);
+        }
+        assert_eq!(adaptive.interval_ms(), 2_000);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_status() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (mut worker, _rx) = scripted_worker(transport.clone(), vec![op(1, 100, &[2])]);
+        worker.interval = PollInterval::new(
+            10_000,
+            Some(RefreshRange {
+                min_ms: 5_000,
+                max_ms: 20_000,
+            }),
+        );
+        transport.push(Endpoint::Threads("g".to_string()), threads(&[(1, 100)]));
+        let status = worker.status();
+
+        worker.init().await.unwrap();
+        worker.update_board().await.unwrap();
+        let status = status.borrow().clone();
+        assert_eq!(status.refresh_rate_ms, 15_000);
+        assert_eq!(status.threads, 1);
+        assert!(status.last_update_sec > 0);
+    }
+
+    #[tracing_test::traced_test]
+    #[tokio::test]
+    async fn test_resume_from_checkpoint() {
+        let transport = Arc::new(ScriptedTransport::new());
+        let (worker, mut rx) = scripted_worker(
+            transport.clone(),
+            vec![op(1, 100, &[2, 3]), op(5, 100, &[])],
+        );
+        let store = Arc::new(MemoryCheckpointStore::new());
+        store
+            .save(&Checkpoint {
+                board: "g".to_string(),
+                last_post_no: 3,
+                threads: HashMap::from([(1, 2), (7, 8)]),
+                seq: 0,
+            })
+            .unwrap();
+        let mut worker = worker.with_checkpoints(store.clone());
+        transport.push(
+            Endpoint::Threads("g".to_string()),
+            threads(&[(1, 100), (5, 100)]),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 1),
+            thread(&[(1, 90), (2, 100), (3, 100)]),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 5),
+            thread(&[(5, 100), (6, 100)]),
+        );
+        transport.push(
+            Endpoint::Thread("g".to_string(), 7),
+            thread(&[(7, 80), (8, 90), (9, 95)]),
+        );
+
+        worker.init().await.unwrap();
+        assert_eq!(received(&mut rx), vec![9]);
+        worker.update_board().await.unwrap();
+        assert_eq!(received(&mut rx), vec![3, 5, 6]);
+
+        worker.save_checkpoint();
+        let checkpoint = store.load("g").unwrap().unwrap();
+        assert_eq!(checkpoint.threads, HashMap::from([(1, 3), (5, 6)]));
+        assert_eq!(checkpoint.last_post_no, 6);
+    }
+
     #[tracing_test::traced_test]
     #[tokio::test]
     async fn test_run() {
         let client = Arc::new(Client::default());
-        let cfg = Subscription {
-            board_name: "pol".to_string(),
-            refresh_rate_ms: 10000,
-        };
+        let cfg = Subscription::new("pol".to_string(), Some(10000));
         let board = Board {
             board: "g".to_string(),
             title: "Technology".to_string(),
@@ -297,11 +2241,12 @@ mod tests {
             is_archived: None,
             forced_anon: None,
             board_flags: None,
+            ..Default::default()
         };
 
         let (tx, mut rx) = tokio::sync::mpsc::channel(100);
         tokio::spawn(async move {
-            if let Err(e) = BoardWorker::new_and_run(client, cfg, board, tx, None).await {
+            if let Err(e) = BoardWorker::new_and_run(client, cfg, board, tx, None, None).await {
                 error!("Error in worker: {:?}", e);
             }
         });
@@ -320,10 +2265,7 @@ mod tests {
     #[tokio::test]
     async fn test_kill() {
         let client = Arc::new(Client::default());
-        let cfg = Subscription {
-            board_name: "pol".to_string(),
-            refresh_rate_ms: 10000,
-        };
+        let cfg = Subscription::new("pol".to_string(), Some(10000));
         let board = Board {
             board: "g".to_string(),
             title: "Technology".to_string(),
@@ -347,12 +2289,15 @@ mod tests {
             is_archived: None,
             forced_anon: None,
             board_flags: None,
+            ..Default::default()
         };
 
         let (tx, _rx) = tokio::sync::mpsc::channel(100);
         let (kill_tx, kill_rx) = tokio::sync::oneshot::channel();
         tokio::spawn(async move {
-            if let Err(e) = BoardWorker::new_and_run(client, cfg, board, tx, Some(kill_rx)).await {
+            if let Err(e) =
+                BoardWorker::new_and_run(client, cfg, board, tx, Some(kill_rx), None).await
+            {
                 error!("Error in worker: {:?}", e);
             }
         });
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+use super::post::{Post, Thread};
+
+/// The threads in a board's archive, as listed by archive.json.
+/// Thread numbers are ordered from oldest to most recently archived.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Archive {
+    pub board: String,
+    pub threads: Vec<i32>,
+}
+
+impl Archive {
+    pub fn new(board: String, threads: Vec<i32>) -> Self {
+        Self { board, threads }
+    }
+
+    pub fn contains(&self, no: i32) -> bool {
+        self.threads.contains(&no)
+    }
+
+    /// What happened to a thread that 404s: archived if the archive lists it,
+    /// otherwise it was pruned or deleted.
+    pub fn status(&self, no: i32) -> ThreadStatus {
+        if self.contains(no) {
+            ThreadStatus::Archived { archived_on: None }
+        } else {
+            ThreadStatus::Removed
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    /// Number of pages of `per_page` threads.
+    pub fn pages(&self, per_page: usize) -> usize {
+        self.threads.len().div_ceil(per_page.max(1))
+    }
+
+    /// A page of thread numbers, most recently archived first.
+    pub fn page(&self, page: usize, per_page: usize) -> &[i32] {
+        let per_page = per_page.max(1);
+        let end = self.threads.len().saturating_sub(page * per_page);
+        let start = end.saturating_sub(per_page);
+        &self.threads[start..end]
+    }
+}
+
+/// A thread from the archive with its final state.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchivedThread {
+    pub no: i32,
+    pub archived_on: Option<i64>,
+    pub thread: std::sync::Arc<Thread>,
+}
+
+/// What happened to a thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThreadStatus {
+    /// Still on the board.
+    Live,
+    /// Moved to the archive, read-only.
+    Archived { archived_on: Option<i64> },
+    /// Gone without being archived: pruned, or deleted by a moderator.
+    Removed,
+}
+
+impl Thread {
+    pub fn op(&self) -> Option<&Post> {
+        self.posts.first()
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.op()
+            .is_some_and(|op| op.archived.is_some_and(|a| a != 0))
+    }
+
+    pub fn archived_on(&self) -> Option<i64> {
+        self.op().and_then(|op| op.archived_on)
+    }
+
+    pub fn status(&self) -> ThreadStatus {
+        if self.is_archived() {
+            ThreadStatus::Archived {
+                archived_on: self.archived_on(),
+            }
+        } else {
+            ThreadStatus::Live
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_pages() {
+        let archive = Archive::new("g".to_string(), (1..=5).collect());
+        assert_eq!(archive.pages(2), 3);
+        assert_eq!(archive.page(0, 2), &[4, 5]);
+        assert_eq!(archive.page(1, 2), &[2, 3]);
+        assert_eq!(archive.page(2, 2), &[1]);
+        assert!(archive.page(3, 2).is_empty());
+        assert!(archive.contains(3));
+        assert_eq!(
+            archive.status(3),
+            ThreadStatus::Archived { archived_on: None }
+        );
+        assert_eq!(archive.status(6), ThreadStatus::Removed);
+    }
+
+    #[test]
+    fn test_thread_status() {
+        let mut thread = Thread {
+            posts: vec![Post {
+                no: 1,
+                ..Default::default()
+            }],
+        };
+        assert_eq!(thread.status(), ThreadStatus::Live);
+        thread.posts[0].archived = Some(1);
+        thread.posts[0].archived_on = Some(1000);
+        assert_eq!(
+            thread.status(),
+            ThreadStatus::Archived {
+                archived_on: Some(1000)
+            }
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Board {
     pub board: String,
     pub title: String,
@@ -20,9 +20,20 @@ pub struct Board {
     pub is_archived: Option<i32>,
     pub forced_anon: Option<i32>,
     pub board_flags: Option<std::collections::HashMap<String, String>>,
+    pub country_flags: Option<i32>,
+    pub user_ids: Option<i32>,
+    pub oekaki: Option<i32>,
+    pub sjis_tags: Option<i32>,
+    pub code_tags: Option<i32>,
+    pub math_tags: Option<i32>,
+    pub text_only: Option<i32>,
+    pub webm_audio: Option<i32>,
+    pub require_subject: Option<i32>,
+    pub min_image_width: Option<i32>,
+    pub min_image_height: Option<i32>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Cooldowns {
     pub threads: i32,
     pub replies: i32,
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+use super::board::{Board, Cooldowns};
+
+/// What a board supports, interpreted from the raw `Board` metadata.
+/// See https://github.com/4chan/4chan-API/blob/master/pages/Boards.md
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoardCapabilities {
+    pub board: String,
+    pub worksafe: bool,
+    pub archived: bool,
+    pub forced_anon: bool,
+    pub spoilers: bool,
+    /// Number of custom spoiler images, 0 if none.
+    pub custom_spoilers: i32,
+    pub country_flags: bool,
+    /// Flag code to flag name.
+    pub board_flags: HashMap<String, String>,
+    pub user_ids: bool,
+    pub oekaki: bool,
+    pub text_only: bool,
+    pub require_subject: bool,
+    pub code_tags: bool,
+    pub math_tags: bool,
+    pub sjis_tags: bool,
+    pub webm_audio: bool,
+    pub limits: Limits,
+    pub cooldowns: Cooldowns,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub max_filesize: i32,
+    pub max_webm_filesize: i32,
+    pub max_comment_chars: i32,
+    pub max_webm_duration: i32,
+    pub min_image_width: Option<i32>,
+    pub min_image_height: Option<i32>,
+    pub bump_limit: i32,
+    pub image_limit: i32,
+}
+
+/// A post about to be made, to check against a board's limits.
+#[derive(Clone, Debug, Default)]
+pub struct DraftPost {
+    /// Whether the post starts a new thread.
+    pub is_op: bool,
+    pub name: Option<String>,
+    pub subject: Option<String>,
+    pub comment: Option<String>,
+    pub spoiler: bool,
+    pub board_flag: Option<String>,
+    pub file: Option<DraftFile>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DraftFile {
+    pub filename: String,
+    /// Size in bytes.
+    pub size: i32,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// Duration in seconds, for WebMs.
+    pub duration: Option<i32>,
+    /// Whether a WebM has an audio track.
+    pub has_audio: bool,
+}
+
+impl DraftFile {
+    pub fn is_webm(&self) -> bool {
+        self.filename.to_lowercase().ends_with(".webm")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum Violation {
+    #[error("A subject is required to start a thread")]
+    SubjectRequired,
+
+    #[error("A file is required to start a thread")]
+    FileRequired,
+
+    #[error("Post has no comment or file")]
+    EmptyPost,
+
+    #[error("Board is text only")]
+    FilesNotAllowed,
+
+    #[error("Board has forced anonymity")]
+    NameNotAllowed,
+
+    #[error("Board does not allow spoilers")]
+    SpoilersNotAllowed,
+
+    #[error("Unknown board flag: {0}")]
+    UnknownBoardFlag(String),
+
+    #[error("Comment too long: {len} > {max} characters")]
+    CommentTooLong { len: i32, max: i32 },
+
+    #[error("File too large: {size} > {max} bytes")]
+    FileTooLarge { size: i32, max: i32 },
+
+    #[error("WebM too long: {duration} > {max} seconds")]
+    WebmTooLong { duration: i32, max: i32 },
+
+    #[error("Board does not allow WebMs with audio")]
+    WebmAudioNotAllowed,
+
+    #[error("Image too small: {width}x{height} < {min_width}x{min_height}")]
+    ImageTooSmall {
+        width: i32,
+        height: i32,
+        min_width: i32,
+        min_height: i32,
+    },
+}
+
+impl BoardCapabilities {
+    pub fn new(board: &Board) -> Self {
+        let flag = |v: Option<i32>| v.is_some_and(|v| v != 0);
+        Self {
+            board: board.board.clone(),
+            worksafe: board.ws_board != 0,
+            archived: flag(board.is_archived),
+            forced_anon: flag(board.forced_anon),
+            spoilers: flag(board.spoilers),
+            custom_spoilers: board.custom_spoilers.unwrap_or(0),
+            country_flags: flag(board.country_flags),
+            board_flags: board.board_flags.clone().unwrap_or_default(),
+            user_ids: flag(board.user_ids),
+            oekaki: flag(board.oekaki),
+            text_only: flag(board.text_only),
+            require_subject: flag(board.require_subject),
+            code_tags: flag(board.code_tags),
+            math_tags: flag(board.math_tags),
+            sjis_tags: flag(board.sjis_tags),
+            webm_audio: flag(board.webm_audio),
+            limits: Limits {
+                max_filesize: board.max_filesize,
+                max_webm_filesize: board.max_webm_filesize,
+                max_comment_chars: board.max_comment_chars,
+                max_webm_duration: board.max_webm_duration,
+                min_image_width: board.min_image_width,
+                min_image_height: board.min_image_height,
+                bump_limit: board.bump_limit,
+                image_limit: board.image_limit,
+            },
+            cooldowns: board.cooldowns.clone(),
+        }
+    }
+
+    pub fn has_board_flags(&self) -> bool {
+        !self.board_flags.is_empty()
+    }
+
+    /// Check a draft post against the board's rules and limits.
+    /// Returns every violation found, not just the first.
+    pub fn validate(&self, draft: &DraftPost) -> Result<(), Vec<Violation>> {
+        let mut violations = vec![];
+        let subject_empty = draft.subject.as_ref().is_none_or(|s| s.trim().is_empty());
+        let comment_len = draft
+            .comment
+            .as_ref()
+            .map_or(0, |c| c.chars().count() as i32);
+
+        if draft.is_op && self.require_subject && subject_empty {
+            violations.push(Violation::SubjectRequired);
+        }
+        if draft.is_op && !self.text_only && draft.file.is_none() {
+            violations.push(Violation::FileRequired);
+        }
+        if !draft.is_op && comment_len == 0 && draft.file.is_none() {
+            violations.push(Violation::EmptyPost);
+        }
+        if comment_len > self.limits.max_comment_chars {
+            violations.push(Violation::CommentTooLong {
+                len: comment_len,
+                max: self.limits.max_comment_chars,
+            });
+        }
+        if self.forced_anon && draft.name.as_ref().is_some_and(|n| !n.trim().is_empty()) {
+            violations.push(Violation::NameNotAllowed);
+        }
+        if draft.spoiler && !self.spoilers {
+            violations.push(Violation::SpoilersNotAllowed);
+        }
+        if let Some(flag) = &draft.board_flag {
+            if !self.board_flags.contains_key(flag) {
+                violations.push(Violation::UnknownBoardFlag(flag.clone()));
+            }
+        }
+        if let Some(file) = &draft.file {
+            if let Err(file_violations) = self.validate_file(file) {
+                violations.extend(file_violations);
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Check a file against the board's size, duration and dimension limits,
+    /// and WebM audio against whether the board allows it.
+    pub fn validate_file(&self, file: &DraftFile) -> Result<(), Vec<Violation>> {
+        if self.text_only {
+            return Err(vec![Violation::FilesNotAllowed]);
+        }
+        let mut violations = vec![];
+        let max_size = if file.is_webm() {
+            self.limits.max_webm_filesize
+        } else {
+            self.limits.max_filesize
+        };
+        if file.size > max_size {
+            violations.push(Violation::FileTooLarge {
+                size: file.size,
+                max: max_size,
+            });
+        }
+        if let Some(duration) = file.duration.filter(|_| file.is_webm()) {
+            if duration > self.limits.max_webm_duration {
+                violations.push(Violation::WebmTooLong {
+                    duration,
+                    max: self.limits.max_webm_duration,
+                });
+            }
+        }
+        if file.is_webm() && file.has_audio && !self.webm_audio {
+            violations.push(Violation::WebmAudioNotAllowed);
+        }
+        if let (Some(width), Some(height)) = (file.width, file.height) {
+            let min_width = self.limits.min_image_width.unwrap_or(0);
+            let min_height = self.limits.min_image_height.unwrap_or(0);
+            if width < min_width || height < min_height {
+                violations.push(Violation::ImageTooSmall {
+                    width,
+                    height,
+                    min_width,
+                    min_height,
+                });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+impl Board {
+    pub fn capabilities(&self) -> BoardCapabilities {
+        BoardCapabilities::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board() -> Board {
+        Board {
+            board: "g".to_string(),
+            ws_board: 1,
+            max_filesize: 4194304,
+            max_webm_filesize: 3145728,
+            max_comment_chars: 10,
+            max_webm_duration: 120,
+            bump_limit: 310,
+            image_limit: 150,
+            is_archived: Some(1),
+            code_tags: Some(1),
+            board_flags: Some(HashMap::from([("GNU".to_string(), "GNU".to_string())])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let caps = board().capabilities();
+        assert!(caps.worksafe);
+        assert!(caps.archived);
+        assert!(caps.code_tags);
+        assert!(caps.has_board_flags());
+        assert!(!caps.math_tags);
+        assert!(!caps.forced_anon);
+        assert!(!caps.text_only);
+    }
+
+    #[test]
+    fn test_validate_post() {
+        let caps = board().capabilities();
+        let draft = DraftPost {
+            comment: Some("hello".to_string()),
+            board_flag: Some("GNU".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(caps.validate(&draft), Ok(()));
+
+        let draft = DraftPost {
+            is_op: true,
+            comment: Some("way too long for this board".to_string()),
+            spoiler: true,
+            board_flag: Some("XX".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            caps.validate(&draft),
+            Err(vec![
+                Violation::FileRequired,
+                Violation::CommentTooLong { len: 27, max: 10 },
+                Violation::SpoilersNotAllowed,
+                Violation::UnknownBoardFlag("XX".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_validate_file() {
+        let caps = board().capabilities();
+        let webm = DraftFile {
+            filename: "clip.webm".to_string(),
+            size: 4000000,
+            duration: Some(200),
+            ..Default::default()
+        };
+        assert_eq!(
+            caps.validate_file(&webm),
+            Err(vec![
+                Violation::FileTooLarge {
+                    size: 4000000,
+                    max: 3145728
+                },
+                Violation::WebmTooLong {
+                    duration: 200,
+                    max: 120
+                },
+            ])
+        );
+
+        let image = DraftFile {
+            filename: "image.png".to_string(),
+            size: 4000000,
+            ..Default::default()
+        };
+        assert_eq!(caps.validate_file(&image), Ok(()));
+
+        let with_audio = DraftFile {
+            filename: "clip.webm".to_string(),
+            size: 1000,
+            has_audio: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            caps.validate_file(&with_audio),
+            Err(vec![Violation::WebmAudioNotAllowed])
+        );
+        let mut audio_board = board();
+        audio_board.webm_audio = Some(1);
+        assert_eq!(
+            audio_board.capabilities().validate_file(&with_audio),
+            Ok(())
+        );
+
+        let mut text_board = board();
+        text_board.text_only = Some(1);
+        assert_eq!(
+            text_board.capabilities().validate_file(&image),
+            Err(vec![Violation::FilesNotAllowed])
+        );
+    }
+}
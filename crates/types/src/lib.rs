@@ -1,5 +1,9 @@
+pub mod archive;
 pub mod board;
+pub mod capabilities;
 pub mod catalog;
 pub mod post;
+pub mod stats;
 pub mod index;
+pub mod link;
 pub mod utils;
@@ -0,0 +1,384 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use super::{board::Board, post::Post};
+
+/// A link to a page on the 4chan website.
+/// Parses the links users paste, e.g.
+/// https://boards.4chan.org/g/thread/99566851/slug#p99567000
+/// and renders canonical web urls for boards, threads and posts.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Link {
+    Board {
+        board: String,
+        page: Option<i32>,
+    },
+    Catalog {
+        board: String,
+    },
+    Archive {
+        board: String,
+    },
+    Thread {
+        board: String,
+        thread: i32,
+        slug: Option<String>,
+    },
+    Post {
+        board: String,
+        thread: i32,
+        post: i32,
+        slug: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LinkError {
+    #[error("Not a 4chan link: {0}")]
+    UnknownHost(String),
+
+    #[error("Invalid path: {0}")]
+    InvalidPath(String),
+
+    #[error("Invalid number: {0}")]
+    InvalidNumber(String),
+}
+
+impl Link {
+    const BASE_URL: &'static str = "https://boards.4chan.org";
+    const HOSTS: [&'static str; 4] = [
+        "boards.4chan.org",
+        "boards.4channel.org",
+        "4chan.org",
+        "4channel.org",
+    ];
+
+    pub fn board(&self) -> &str {
+        match self {
+            Self::Board { board, .. }
+            | Self::Catalog { board }
+            | Self::Archive { board }
+            | Self::Thread { board, .. }
+            | Self::Post { board, .. } => board,
+        }
+    }
+
+    pub fn thread_no(&self) -> Option<i32> {
+        match self {
+            Self::Thread { thread, .. } | Self::Post { thread, .. } => Some(*thread),
+            _ => None,
+        }
+    }
+
+    /// The post a link points to.
+    /// A thread link points to its OP.
+    pub fn post_no(&self) -> Option<i32> {
+        match self {
+            Self::Thread { thread, .. } => Some(*thread),
+            Self::Post { post, .. } => Some(*post),
+            _ => None,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn parse(input: &str) -> Result<Link, LinkError> {
+        let trimmed = input.trim();
+        let without_scheme = trimmed
+            .strip_prefix("https://")
+            .or_else(|| trimmed.strip_prefix("http://"))
+            .or_else(|| trimmed.strip_prefix("//"))
+            .unwrap_or(trimmed);
+
+        let (rest, fragment) = match without_scheme.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(fragment)),
+            None => (without_scheme, None),
+        };
+        let rest = rest.split_once('?').map_or(rest, |(rest, _)| rest);
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+
+        if !Self::HOSTS.contains(&host.to_lowercase().as_str()) {
+            return Err(LinkError::UnknownHost(host.to_string()));
+        }
+
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let link = match segments.as_slice() {
+            [board] => Self::Board {
+                board: board.to_string(),
+                page: None,
+            },
+            [board, "catalog"] => Self::Catalog {
+                board: board.to_string(),
+            },
+            [board, "archive"] => Self::Archive {
+                board: board.to_string(),
+            },
+            [board, page] => Self::Board {
+                board: board.to_string(),
+                page: Some(Self::parse_number(page)?),
+            },
+            [board, "thread", thread] | [board, "thread", thread, ..] => {
+                let slug = segments.get(3).map(|s| s.to_string());
+                let thread = Self::parse_number(thread)?;
+                match fragment.and_then(Self::parse_anchor) {
+                    Some(post) if post != thread => Self::Post {
+                        board: board.to_string(),
+                        thread,
+                        post,
+                        slug,
+                    },
+                    _ => Self::Thread {
+                        board: board.to_string(),
+                        thread,
+                        slug,
+                    },
+                }
+            }
+            _ => return Err(LinkError::InvalidPath(path.to_string())),
+        };
+        Ok(link)
+    }
+
+    /// Post anchors are either #p<no> (link to post) or #q<no> (quote post).
+    /// Any other anchor is ignored.
+    fn parse_anchor(fragment: &str) -> Option<i32> {
+        fragment
+            .strip_prefix('p')
+            .or_else(|| fragment.strip_prefix('q'))
+            .and_then(|no| no.parse().ok())
+    }
+
+    fn parse_number(input: &str) -> Result<i32, LinkError> {
+        input
+            .parse()
+            .map_err(|_| LinkError::InvalidNumber(input.to_string()))
+    }
+}
+
+impl FromStr for Link {
+    type Err = LinkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Link::parse(s)
+    }
+}
+
+impl Display for Link {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Board { board, page } => match page {
+                Some(page) if *page > 1 => write!(f, "{}/{}/{}", Self::BASE_URL, board, page),
+                _ => write!(f, "{}/{}/", Self::BASE_URL, board),
+            },
+            Self::Catalog { board } => write!(f, "{}/{}/catalog", Self::BASE_URL, board),
+            Self::Archive { board } => write!(f, "{}/{}/archive", Self::BASE_URL, board),
+            Self::Thread {
+                board,
+                thread,
+                slug,
+            } => {
+                write!(f, "{}/{}/thread/{}", Self::BASE_URL, board, thread)?;
+                if let Some(slug) = slug {
+                    write!(f, "/{}", slug)?;
+                }
+                Ok(())
+            }
+            Self::Post {
+                board,
+                thread,
+                post,
+                slug,
+            } => {
+                write!(f, "{}/{}/thread/{}", Self::BASE_URL, board, thread)?;
+                if let Some(slug) = slug {
+                    write!(f, "/{}", slug)?;
+                }
+                write!(f, "#p{}", post)
+            }
+        }
+    }
+}
+
+impl Post {
+    /// Link to this post on the given board.
+    /// OPs link to their thread, replies link to the thread with a post anchor.
+    pub fn link(&self, board: &str) -> Link {
+        if self.is_op() {
+            Link::Thread {
+                board: board.to_string(),
+                thread: self.no,
+                slug: self.semantic_url.clone(),
+            }
+        } else {
+            Link::Post {
+                board: board.to_string(),
+                thread: self.thread_no(),
+                post: self.no,
+                slug: None,
+            }
+        }
+    }
+}
+
+impl Board {
+    pub fn link(&self) -> Link {
+        Link::Board {
+            board: self.board.clone(),
+            page: None,
+        }
+    }
+
+    pub fn catalog_link(&self) -> Link {
+        Link::Catalog {
+            board: self.board.clone(),
+        }
+    }
+
+    pub fn archive_link(&self) -> Link {
+        Link::Archive {
+            board: self.board.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_post_link() {
+        let link: Link = "https://boards.4chan.org/g/thread/99566851/slug#p99567000"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            link,
+            Link::Post {
+                board: "g".to_string(),
+                thread: 99566851,
+                post: 99567000,
+                slug: Some("slug".to_string()),
+            }
+        );
+        assert_eq!(link.post_no(), Some(99567000));
+        assert_eq!(
+            link.url(),
+            "https://boards.4chan.org/g/thread/99566851/slug#p99567000"
+        );
+    }
+
+    #[test]
+    fn test_parse_quote_anchor() {
+        let link = Link::parse("boards.4channel.org/v/thread/123#q456").unwrap();
+        assert_eq!(
+            link,
+            Link::Post {
+                board: "v".to_string(),
+                thread: 123,
+                post: 456,
+                slug: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_thread_link() {
+        let link = Link::parse("http://boards.4chan.org/g/thread/123/?foo=bar#p123").unwrap();
+        assert_eq!(
+            link,
+            Link::Thread {
+                board: "g".to_string(),
+                thread: 123,
+                slug: None,
+            }
+        );
+        assert_eq!(link.url(), "https://boards.4chan.org/g/thread/123");
+
+        let link = Link::parse("https://4chan.org/g/thread/123#pbottom").unwrap();
+        assert_eq!(
+            link,
+            Link::Thread {
+                board: "g".to_string(),
+                thread: 123,
+                slug: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_board_links() {
+        assert_eq!(
+            Link::parse("https://boards.4chan.org/g/").unwrap(),
+            Link::Board {
+                board: "g".to_string(),
+                page: None
+            }
+        );
+        assert_eq!(
+            Link::parse("https://boards.4chan.org/g/3").unwrap(),
+            Link::Board {
+                board: "g".to_string(),
+                page: Some(3)
+            }
+        );
+        assert_eq!(
+            Link::parse("https://boards.4chan.org/g/catalog").unwrap(),
+            Link::Catalog {
+                board: "g".to_string()
+            }
+        );
+        assert_eq!(
+            Link::parse("https://boards.4chan.org/g/archive").unwrap(),
+            Link::Archive {
+                board: "g".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            Link::parse("https://example.com/g/"),
+            Err(LinkError::UnknownHost(_))
+        ));
+        assert!(matches!(
+            Link::parse("https://boards.4chan.org/g/thread/abc"),
+            Err(LinkError::InvalidNumber(_))
+        ));
+        assert!(matches!(
+            Link::parse("https://boards.4chan.org/"),
+            Err(LinkError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn test_post_link() {
+        let op = Post {
+            no: 123,
+            resto: Some(0),
+            semantic_url: Some("slug".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            op.link("g").url(),
+            "https://boards.4chan.org/g/thread/123/slug"
+        );
+
+        let reply = Post {
+            no: 456,
+            resto: Some(123),
+            ..Default::default()
+        };
+        assert_eq!(
+            reply.link("g").url(),
+            "https://boards.4chan.org/g/thread/123#p456"
+        );
+        assert_eq!(
+            Link::parse(&reply.link("g").url()).unwrap(),
+            reply.link("g")
+        );
+    }
+}
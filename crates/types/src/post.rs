@@ -12,13 +12,14 @@ pub struct ThreadPage {
     pub threads: Vec<Post>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Post {
     pub no: i32,
     pub sticky: Option<i32>,
     pub closed: Option<i32>,
     pub now: Option<String>,
     pub name: Option<String>,
+    pub trip: Option<String>,
     pub sub: Option<String>,
     pub com: Option<String>,
     pub filename: Option<String>,
@@ -32,7 +33,12 @@ pub struct Post {
     pub md5: Option<String>,
     pub fsize: Option<i32>,
     pub resto: Option<i32>,
+    pub id: Option<String>,
     pub capcode: Option<String>,
+    pub country: Option<String>,
+    pub country_name: Option<String>,
+    pub board_flag: Option<String>,
+    pub flag_name: Option<String>,
     pub semantic_url: Option<String>,
     pub replies: Option<i32>,
     pub images: Option<i32>,
@@ -41,6 +47,11 @@ pub struct Post {
     pub omitted_images: Option<i32>,
     pub last_replies: Option<Vec<Post>>,
     pub last_modified: Option<i64>,
+    pub archived: Option<i32>,
+    pub archived_on: Option<i64>,
+    pub bumplimit: Option<i32>,
+    pub imagelimit: Option<i32>,
+    pub filedeleted: Option<i32>,
 }
 
 impl Post {
@@ -75,10 +86,22 @@ impl Post {
         self.closed.is_some()
     }
 
+    pub fn bump_limit_reached(&self) -> bool {
+        self.bumplimit.is_some_and(|b| b != 0)
+    }
+
+    pub fn image_limit_reached(&self) -> bool {
+        self.imagelimit.is_some_and(|i| i != 0)
+    }
+
     pub fn has_image(&self) -> bool {
         self.tim.is_some()
     }
 
+    pub fn is_file_deleted(&self) -> bool {
+        self.filedeleted.is_some_and(|f| f != 0)
+    }
+
     pub fn has_replies(&self) -> bool {
         self.replies.is_some()
     }
@@ -111,7 +134,7 @@ impl Eq for Post {}
 
 impl PartialOrd for Post {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.no.cmp(&other.no))
+        Some(self.cmp(other))
     }
 }
 
@@ -132,6 +155,7 @@ mod tests {
             closed: None,
             now: None,
             name: None,
+            trip: None,
             sub: None,
             com: Some(comment.to_string()),
             filename: None,
@@ -145,7 +169,12 @@ mod tests {
             md5: None,
             fsize: None,
             resto: None,
+            id: None,
             capcode: None,
+            country: None,
+            country_name: None,
+            board_flag: None,
+            flag_name: None,
             semantic_url: None,
             replies: None,
             images: None,
@@ -154,6 +183,11 @@ mod tests {
             omitted_images: None,
             last_replies: None,
             last_modified: None,
+            archived: None,
+            archived_on: None,
+            bumplimit: None,
+            imagelimit: None,
+            filedeleted: None,
         }
     }
 
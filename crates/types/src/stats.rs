@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use super::{
+    board::Board,
+    catalog::CatalogPage,
+    post::{Post, Thread, ThreadPage},
+};
+
+/// Activity metrics for a single thread.
+/// All times are unix timestamps in seconds, `now` is passed in by the caller
+/// so the same snapshot can be evaluated at different points in time.
+#[derive(Clone, Debug)]
+pub struct ThreadStats<'a> {
+    thread: &'a Thread,
+}
+
+impl<'a> ThreadStats<'a> {
+    pub fn new(thread: &'a Thread) -> Self {
+        Self { thread }
+    }
+
+    pub fn op(&self) -> Option<&Post> {
+        self.thread.posts.first()
+    }
+
+    pub fn post_count(&self) -> usize {
+        self.thread.posts.len()
+    }
+
+    pub fn reply_count(&self) -> usize {
+        self.post_count().saturating_sub(1)
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.thread.posts.iter().filter(|p| p.has_image()).count()
+    }
+
+    /// Fraction of posts carrying a file, between 0 and 1.
+    pub fn image_ratio(&self) -> f64 {
+        if self.thread.posts.is_empty() {
+            return 0.0;
+        }
+        self.image_count() as f64 / self.post_count() as f64
+    }
+
+    /// Posts made in the window (now - window_secs, now], per minute.
+    pub fn posts_per_minute(&self, window_secs: i64, now: i64) -> f64 {
+        if window_secs <= 0 {
+            return 0.0;
+        }
+        let since = now - window_secs;
+        let n = self
+            .thread
+            .posts
+            .iter()
+            .filter(|p| p.time.is_some_and(|t| t > since && t <= now))
+            .count();
+        n as f64 * 60.0 / window_secs as f64
+    }
+
+    /// Posts per minute over each of the given windows.
+    pub fn posts_per_minute_windows(&self, windows_secs: &[i64], now: i64) -> Vec<(i64, f64)> {
+        windows_secs
+            .iter()
+            .map(|w| (*w, self.posts_per_minute(*w, now)))
+            .collect()
+    }
+
+    /// Estimated seconds until the thread reaches the board's bump limit,
+    /// extrapolating the reply rate over the given window.
+    /// Returns None if the thread has no recent activity.
+    pub fn time_to_bump_limit(&self, board: &Board, window_secs: i64, now: i64) -> Option<i64> {
+        let remaining = board.bump_limit as i64 - self.reply_count() as i64;
+        if remaining <= 0 {
+            return Some(0);
+        }
+        let rate = self.posts_per_minute(window_secs, now);
+        if rate <= 0.0 {
+            return None;
+        }
+        Some((remaining as f64 / rate * 60.0).ceil() as i64)
+    }
+
+    /// Number of posts per poster ID.
+    /// Empty on boards without poster IDs.
+    pub fn poster_ids(&self) -> HashMap<String, usize> {
+        let mut ids = HashMap::new();
+        for id in self.thread.posts.iter().filter_map(|p| p.id.as_ref()) {
+            *ids.entry(id.clone()).or_insert(0) += 1;
+        }
+        ids
+    }
+
+    /// The longest chain of posts where each post quotes the previous one,
+    /// in post order.
+    pub fn longest_reply_chain(&self) -> Vec<i32> {
+        // Quotes always point backwards, so walking posts in order
+        // visits every quoted post before the posts quoting it.
+        let mut best: HashMap<i32, (usize, Option<i32>)> = HashMap::new();
+        let mut longest: Option<(usize, i32)> = None;
+        for post in &self.thread.posts {
+            let parent = post
+                .quotes()
+                .into_iter()
+                .filter_map(|q| best.get(&q).map(|(len, _)| (*len, q)))
+                .max();
+            let len = parent.map_or(1, |(len, _)| len + 1);
+            best.insert(post.no, (len, parent.map(|(_, q)| q)));
+            if longest.is_none_or(|(l, _)| len > l) {
+                longest = Some((len, post.no));
+            }
+        }
+
+        let mut chain = vec![];
+        let mut current = longest.map(|(_, no)| no);
+        while let Some(no) = current {
+            chain.push(no);
+            current = best.get(&no).and_then(|(_, parent)| *parent);
+        }
+        chain.reverse();
+        chain
+    }
+}
+
+impl Thread {
+    pub fn stats(&self) -> ThreadStats<'_> {
+        ThreadStats::new(self)
+    }
+}
+
+/// Replies per minute since the thread was created, from the OP alone.
+/// Useful for ranking threads from threads.json or catalog.json without fetching them.
+pub fn op_velocity(op: &Post, now: i64) -> Option<f64> {
+    let created = op.time?;
+    let minutes = (now - created) as f64 / 60.0;
+    if minutes <= 0.0 {
+        return None;
+    }
+    Some(op.replies.unwrap_or(0) as f64 / minutes)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PagePosition {
+    pub time: i64,
+    pub page: i32,
+    /// Zero-based position of the thread across the whole board.
+    pub position: usize,
+}
+
+/// Page position history of threads across successive snapshots
+/// of threads.json or catalog.json.
+/// Only changes in position are recorded.
+#[derive(Clone, Debug, Default)]
+pub struct PageHistory {
+    threads: HashMap<i32, Vec<PagePosition>>,
+}
+
+impl PageHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_threads(&mut self, time: i64, pages: &[ThreadPage]) {
+        self.record(
+            time,
+            pages
+                .iter()
+                .flat_map(|page| page.threads.iter().map(move |t| (page.page, t.no))),
+        );
+    }
+
+    pub fn record_catalog(&mut self, time: i64, pages: &[CatalogPage]) {
+        self.record(
+            time,
+            pages
+                .iter()
+                .flat_map(|page| page.threads.iter().map(move |t| (page.page, t.no))),
+        );
+    }
+
+    fn record(&mut self, time: i64, threads: impl Iterator<Item = (i32, i32)>) {
+        for (position, (page, no)) in threads.enumerate() {
+            let history = self.threads.entry(no).or_default();
+            let changed = history
+                .last()
+                .is_none_or(|last| last.page != page || last.position != position);
+            if changed {
+                history.push(PagePosition {
+                    time,
+                    page,
+                    position,
+                });
+            }
+        }
+    }
+
+    pub fn history(&self, no: i32) -> &[PagePosition] {
+        self.threads.get(&no).map_or(&[], |h| h.as_slice())
+    }
+
+    /// Forget threads that are no longer on the board.
+    pub fn retain(&mut self, f: impl Fn(i32) -> bool) {
+        self.threads.retain(|no, _| f(*no));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(no: i32, time: i64, com: Option<&str>) -> Post {
+        Post {
+            no,
+            time: Some(time),
+            resto: Some(if no == 1 { 0 } else { 1 }),
+            com: com.map(|c| c.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn quote(no: i32) -> String {
+        format!(
+            "<a href=\"#p{}\" class=\"quotelink\">&gt;&gt;{}</a>",
+            no, no
+        )
+    }
+
+    #[test]
+    fn test_posts_per_minute() {
+        let thread = Thread {
+            posts: vec![post(1, 0, None), post(2, 100, None), post(3, 110, None)],
+        };
+        let stats = thread.stats();
+        assert_eq!(stats.posts_per_minute(60, 120), 2.0);
+        assert_eq!(stats.posts_per_minute(240, 120), 0.75);
+        assert_eq!(
+            stats.posts_per_minute_windows(&[60, 240], 120),
+            vec![(60, 2.0), (240, 0.75)]
+        );
+    }
+
+    #[test]
+    fn test_time_to_bump_limit() {
+        let thread = Thread {
+            posts: vec![post(1, 0, None), post(2, 100, None), post(3, 110, None)],
+        };
+        let board = Board {
+            bump_limit: 4,
+            ..Default::default()
+        };
+        // 2 replies left at 2 posts per minute
+        assert_eq!(thread.stats().time_to_bump_limit(&board, 60, 120), Some(60));
+        assert_eq!(thread.stats().time_to_bump_limit(&board, 60, 1000), None);
+    }
+
+    #[test]
+    fn test_image_ratio_and_ids() {
+        let mut thread = Thread {
+            posts: vec![post(1, 0, None), post(2, 1, None)],
+        };
+        thread.posts[0].tim = Some(1);
+        thread.posts[0].id = Some("abc".to_string());
+        thread.posts[1].id = Some("abc".to_string());
+        let stats = thread.stats();
+        assert_eq!(stats.image_ratio(), 0.5);
+        assert_eq!(stats.poster_ids().get("abc"), Some(&2));
+    }
+
+    #[test]
+    fn test_longest_reply_chain() {
+        let thread = Thread {
+            posts: vec![
+                post(1, 0, None),
+                post(2, 1, Some(&quote(1))),
+                post(3, 2, Some(&quote(1))),
+                post(4, 3, Some(&quote(2))),
+                post(5, 4, Some(&format!("{}<br>{}", quote(3), quote(4)))),
+            ],
+        };
+        assert_eq!(thread.stats().longest_reply_chain(), vec![1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn test_page_history() {
+        let page = |page: i32, nos: &[i32]| ThreadPage {
+            page,
+            threads: nos.iter().map(|no| post(*no, 0, None)).collect(),
+        };
+        let mut history = PageHistory::new();
+        history.record_threads(0, &[page(1, &[1, 2]), page(2, &[3])]);
+        history.record_threads(10, &[page(1, &[1, 2]), page(2, &[3])]);
+        history.record_threads(20, &[page(1, &[3, 1]), page(2, &[2])]);
+        assert_eq!(
+            history.history(2),
+            &[
+                PagePosition {
+                    time: 0,
+                    page: 1,
+                    position: 1
+                },
+                PagePosition {
+                    time: 20,
+                    page: 2,
+                    position: 2
+                },
+            ]
+        );
+        assert_eq!(history.history(1).len(), 2);
+        assert!(history.history(4).is_empty());
+    }
+}